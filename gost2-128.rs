@@ -21,21 +21,26 @@
 
 // --- Rust port notes:
 // - This is a direct translation of the original C code into Rust.
-// - Global mutable state (x1, x2, h1, h2, k-tables) is kept as `static mut` and accessed within `unsafe` blocks,
-//   to stay faithful to the original structure.
+// - The original C globals (x1, x2, h1, h2, k-tables) are owned fields on
+//   `Md2iiHasher`/`Gost2Params` instead of `static mut`, so independent
+//   hashing/encryption can run concurrently without shared mutable state.
 // - Arithmetic uses wrapping semantics where C would naturally wrap (e.g., u64 addition).
 // - `create_keys` relies on the caller having a zero-initialized `key` array (as done in `main`),
 //   matching the intended behavior of the C example outputs.
 
 /*
   Cargo.toml
-  * 
+  *
 [package]
 name = "gost2-128"
 version = "0.1.0"
 edition = "2024"
 
 [dependencies]
+cipher = "0.4"
+pqcrypto-kyber = "0.8"
+pqcrypto-dilithium = "0.5"
+pqcrypto-traits = "0.3"
 
 */
 
@@ -45,78 +50,78 @@ type Word64 = u64;
 
 const N1: usize = 512; /* 4096-bit GOST2-128 key for 64 * 64-bit subkeys */
 
-static mut X1: i32 = 0;
-static mut X2: usize = 0;
-
-static mut H2: [u8; N1] = [0; N1];
-static mut H1: [u8; N1 * 3] = [0; N1 * 3];
+// static unsigned char s4[256] = { ... };
+// Keep as const; identical contents, shared read-only by every hasher instance.
+const S4: [u8; 256] = [
+    13,199, 11, 67,237,193,164, 77,115,184,141,222, 73, 38,147, 36,
+   150, 87, 21,104, 12, 61,156,101,111,145,119, 22,207, 35,198, 37,
+   171,167, 80, 30,219, 28,213,121, 86, 29,214,242,  6,  4, 89,162,
+   110,175, 19,157,  3, 88,234, 94,144,118,159,239,100, 17,182,173,
+   238, 68, 16, 79,132, 54,163, 52,  9, 58, 57, 55,229,192,170,226,
+    56,231,187,158, 70,224,233,245, 26, 47, 32, 44,247,  8,251, 20,
+   197,185,109,153,204,218, 93,178,212,137, 84,174, 24,120,130,149,
+    72,180,181,208,255,189,152, 18,143,176, 60,249, 27,227,128,139,
+   243,253, 59,123,172,108,211, 96,138, 10,215, 42,225, 40, 81, 65,
+    90, 25, 98,126,154, 64,124,116,122,  5,  1,168, 83,190,131,191,
+   244,240,235,177,155,228,125, 66, 43,201,248,220,129,188,230, 62,
+    75, 71, 78, 34, 31,216,254,136, 91,114,106, 46,217,196, 92,151,
+   209,133, 51,236, 33,252,127,179, 69,  7,183,105,146, 97, 39, 15,
+   205,112,200,166,223, 45, 48,246,186, 41,148,140,107, 76, 85, 95,
+   194,142, 50, 49,134, 23,135,169,221,210,203, 63,165, 82,161,202,
+    53, 14,206,232,103,102,195,117,250, 99,  0, 74,160,241,  2,113
+];
+
+/// MD2II hash context, holding what used to be the global `X1`/`X2`/`H1`/`H2`
+/// state as owned fields instead. Each instance is independent, so several
+/// threads can each derive subkeys for their own file/region concurrently.
+pub struct Md2iiHasher {
+    x1: i32,
+    x2: usize,
+    h1: Box<[u8; N1 * 3]>,
+    h2: Box<[u8; N1]>,
+}
 
-/* initialize internal state */
-fn init() {
-    unsafe {
-        X1 = 0;
-        X2 = 0;
-        for i in 0..N1 {
-            H2[i] = 0;
-        }
-        for i in 0..N1 {
-            H1[i] = 0;
+impl Md2iiHasher {
+    pub fn new() -> Self {
+        Md2iiHasher {
+            x1: 0,
+            x2: 0,
+            h1: Box::new([0u8; N1 * 3]),
+            h2: Box::new([0u8; N1]),
         }
     }
-}
 
-fn hashing(t1: &[u8], mut b6: usize) {
-    // static unsigned char s4[256] = { ... };
-    // Keep as const; identical contents.
-    const S4: [u8; 256] = [
-        13,199, 11, 67,237,193,164, 77,115,184,141,222, 73, 38,147, 36,
-       150, 87, 21,104, 12, 61,156,101,111,145,119, 22,207, 35,198, 37,
-       171,167, 80, 30,219, 28,213,121, 86, 29,214,242,  6,  4, 89,162,
-       110,175, 19,157,  3, 88,234, 94,144,118,159,239,100, 17,182,173,
-       238, 68, 16, 79,132, 54,163, 52,  9, 58, 57, 55,229,192,170,226,
-        56,231,187,158, 70,224,233,245, 26, 47, 32, 44,247,  8,251, 20,
-       197,185,109,153,204,218, 93,178,212,137, 84,174, 24,120,130,149,
-        72,180,181,208,255,189,152, 18,143,176, 60,249, 27,227,128,139,
-       243,253, 59,123,172,108,211, 96,138, 10,215, 42,225, 40, 81, 65,
-        90, 25, 98,126,154, 64,124,116,122,  5,  1,168, 83,190,131,191,
-       244,240,235,177,155,228,125, 66, 43,201,248,220,129,188,230, 62,
-        75, 71, 78, 34, 31,216,254,136, 91,114,106, 46,217,196, 92,151,
-       209,133, 51,236, 33,252,127,179, 69,  7,183,105,146, 97, 39, 15,
-       205,112,200,166,223, 45, 48,246,186, 41,148,140,107, 76, 85, 95,
-       194,142, 50, 49,134, 23,135,169,221,210,203, 63,165, 82,161,202,
-        53, 14,206,232,103,102,195,117,250, 99,  0, 74,160,241,  2,113
-    ];
-
-    let mut b4: usize = 0;
-    unsafe {
+    pub fn update(&mut self, t1: &[u8]) {
+        let mut b6 = t1.len();
+        let mut b4: usize = 0;
         while b6 > 0 {
-            while b6 > 0 && X2 < N1 {
+            while b6 > 0 && self.x2 < N1 {
                 let b5 = t1[b4] as i32;
                 b4 += 1;
 
-                H1[X2 + N1] = b5 as u8;
-                H1[X2 + (N1 * 2)] = (b5 as u8) ^ H1[X2];
+                self.h1[self.x2 + N1] = b5 as u8;
+                self.h1[self.x2 + (N1 * 2)] = (b5 as u8) ^ self.h1[self.x2];
 
                 // x1 = h2[x2] ^= s4[b5 ^ x1];
-                let idx = ((b5 ^ X1) & 0xFF) as usize;
-                let val = H2[X2] ^ S4[idx];
-                H2[X2] = val;
-                X1 = val as i32;
+                let idx = ((b5 ^ self.x1) & 0xFF) as usize;
+                let val = self.h2[self.x2] ^ S4[idx];
+                self.h2[self.x2] = val;
+                self.x1 = val as i32;
 
                 b6 -= 1;
-                X2 += 1;
+                self.x2 += 1;
             }
 
-            if X2 == N1 {
+            if self.x2 == N1 {
                 let mut b2: i32 = 0;
-                X2 = 0;
+                self.x2 = 0;
 
                 for b3 in 0..(N1 + 2) {
                     for b1 in 0..(N1 * 3) {
                         // b2 = h1[b1] ^= s4[b2];
                         let idx = (b2 & 0xFF) as usize;
-                        let newv = H1[b1] ^ S4[idx];
-                        H1[b1] = newv;
+                        let newv = self.h1[b1] ^ S4[idx];
+                        self.h1[b1] = newv;
                         b2 = newv as i32;
                     }
                     b2 = (b2 + b3 as i32) % 256;
@@ -124,30 +129,34 @@ fn hashing(t1: &[u8], mut b6: usize) {
             }
         }
     }
-}
 
-fn end_fn(h4: &mut [u8; N1]) {
-    unsafe {
-        let n4 = N1 - X2;
+    pub fn finalize(mut self, h4: &mut [u8; N1]) {
+        let n4 = N1 - self.x2;
         let mut h3 = [0u8; N1];
         for i in 0..n4 {
             h3[i] = n4 as u8;
         }
-        hashing(&h3[..n4], n4);
+        self.update(&h3[..n4]);
 
         // hashing(h2, sizeof(h2));
-        // We can pass a snapshot of H2; hashing only *reads* its argument and
-        // updates global state H1/H2 internally, just like C code did.
-        let snapshot_h2: Vec<u8> = H2[..].to_vec();
-        hashing(&snapshot_h2, snapshot_h2.len());
+        // `update` only *reads* its argument and mutates `self`, so a snapshot
+        // of h2 taken before the call is exactly what the C code passed in.
+        let snapshot_h2 = self.h2.to_vec();
+        self.update(&snapshot_h2);
 
         // for (i = 0; i < n1; i++) h4[i] = h1[i];
         for i in 0..N1 {
-            h4[i] = H1[i];
+            h4[i] = self.h1[i];
         }
     }
 }
 
+impl Default for Md2iiHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 
 /* create 64 * 64-bit subkeys from h4 hash */
 fn create_keys(h4: &[u8; N1], key: &mut [Word64; 64]) {
@@ -162,52 +171,112 @@ fn create_keys(h4: &[u8; N1], key: &mut [Word64; 64]) {
     }
 }
 
-static K1:  [u8; 16] = [0x4,0xA,0x9,0x2,0xD,0x8,0x0,0xE,0x6,0xB,0x1,0xC,0x7,0xF,0x5,0x3];
-static K2:  [u8; 16] = [0xE,0xB,0x4,0xC,0x6,0xD,0xF,0xA,0x2,0x3,0x8,0x1,0x0,0x7,0x5,0x9];
-static K3:  [u8; 16] = [0x5,0x8,0x1,0xD,0xA,0x3,0x4,0x2,0xE,0xF,0xC,0x7,0x6,0x0,0x9,0xB];
-static K4:  [u8; 16] = [0x7,0xD,0xA,0x1,0x0,0x8,0x9,0xF,0xE,0x4,0x6,0xC,0xB,0x2,0x5,0x3];
-static K5:  [u8; 16] = [0x6,0xC,0x7,0x1,0x5,0xF,0xD,0x8,0x4,0xA,0x9,0xE,0x0,0x3,0xB,0x2];
-static K6:  [u8; 16] = [0x4,0xB,0xA,0x0,0x7,0x2,0x1,0xD,0x3,0x6,0x8,0x5,0x9,0xC,0xF,0xE];
-static K7:  [u8; 16] = [0xD,0xB,0x4,0x1,0x3,0xF,0x5,0x9,0x0,0xA,0xE,0x7,0x6,0x8,0x2,0xC];
-static K8:  [u8; 16] = [0x1,0xF,0xD,0x0,0x5,0x7,0xA,0x4,0x9,0x2,0x3,0xE,0x6,0xB,0x8,0xC];
-
-static K9:  [u8; 16] = [0xC,0x4,0x6,0x2,0xA,0x5,0xB,0x9,0xE,0x8,0xD,0x7,0x0,0x3,0xF,0x1];
-static K10: [u8; 16] = [0x6,0x8,0x2,0x3,0x9,0xA,0x5,0xC,0x1,0xE,0x4,0x7,0xB,0xD,0x0,0xF];
-static K11: [u8; 16] = [0xB,0x3,0x5,0x8,0x2,0xF,0xA,0xD,0xE,0x1,0x7,0x4,0xC,0x9,0x6,0x0];
-static K12: [u8; 16] = [0xC,0x8,0x2,0x1,0xD,0x4,0xF,0x6,0x7,0x0,0xA,0x5,0x3,0xE,0x9,0xB];
-static K13: [u8; 16] = [0x7,0xF,0x5,0xA,0x8,0x1,0x6,0xD,0x0,0x9,0x3,0xE,0xB,0x4,0x2,0xC];
-static K14: [u8; 16] = [0x5,0xD,0xF,0x6,0x9,0x2,0xC,0xA,0xB,0x7,0x8,0x1,0x4,0x3,0xE,0x0];
-static K15: [u8; 16] = [0x8,0xE,0x2,0x5,0x6,0x9,0x1,0xC,0xF,0x4,0xB,0x0,0xD,0xA,0x3,0x7];
-static K16: [u8; 16] = [0x1,0x7,0xE,0xD,0x0,0x5,0x8,0x3,0x4,0xF,0xA,0x6,0x9,0xC,0xB,0x2];
-
-/* Byte-at-a-time substitution boxes */
-static mut K175: [u8; 256] = [0; 256];
-static mut K153: [u8; 256] = [0; 256];
-static mut K131: [u8; 256] = [0; 256];
-static mut K109: [u8; 256] = [0; 256];
-static mut K87:  [u8; 256] = [0; 256];
-static mut K65:  [u8; 256] = [0; 256];
-static mut K43:  [u8; 256] = [0; 256];
-static mut K21:  [u8; 256] = [0; 256];
+/* The sixteen 4-bit substitution tables, and the eight byte-at-a-time tables
+ * built from them, now live in a `Gost2Params` value instead of globals, so
+ * a cipher instance can pick or supply its own S-boxes instead of always
+ * using the one hardcoded set. */
+#[derive(Clone, Copy)]
+pub struct Gost2Params {
+    sboxes: [[u8; 16]; 16],
+    k175: [u8; 256],
+    k153: [u8; 256],
+    k131: [u8; 256],
+    k109: [u8; 256],
+    k87: [u8; 256],
+    k65: [u8; 256],
+    k43: [u8; 256],
+    k21: [u8; 256],
+}
 
-/*
- * Build byte-at-a-time subtitution tables.
- * This must be called once for global setup.
- */
-fn kboxinit() {
-    unsafe {
+/// Error returned by [`Gost2Params::from_sboxes`] when a supplied table is
+/// not a permutation of 0..=15.
+#[derive(Debug)]
+pub struct InvalidSbox {
+    pub index: usize,
+}
+
+impl Gost2Params {
+    /// Build the eight byte-at-a-time tables from sixteen 4-bit nibble
+    /// tables, after checking each is a permutation of 0..=15 (this is what
+    /// `kboxinit` used to do against the global K1..K16 statics).
+    pub fn from_sboxes(sboxes: [[u8; 16]; 16]) -> Result<Self, InvalidSbox> {
+        for (index, table) in sboxes.iter().enumerate() {
+            let mut seen = [false; 16];
+            for &v in table {
+                if v > 15 || seen[v as usize] {
+                    return Err(InvalidSbox { index });
+                }
+                seen[v as usize] = true;
+            }
+        }
+
+        let mut params = Gost2Params {
+            sboxes,
+            k175: [0; 256], k153: [0; 256], k131: [0; 256], k109: [0; 256],
+            k87: [0; 256], k65: [0; 256], k43: [0; 256], k21: [0; 256],
+        };
+
+        let s = &params.sboxes;
         for i in 0u16..256 {
             let i8 = i as u8;
-            K175[i as usize] = (K16[(i8 >> 4) as usize] << 4) | K15[(i8 & 15) as usize];
-            K153[i as usize] = (K14[(i8 >> 4) as usize] << 4) | K13[(i8 & 15) as usize];
-            K131[i as usize] = (K12[(i8 >> 4) as usize] << 4) | K11[(i8 & 15) as usize];
-            K109[i as usize] = (K10[(i8 >> 4) as usize] << 4) | K9 [(i8 & 15) as usize];
-
-            K87[i as usize]  = (K8 [(i8 >> 4) as usize] << 4) | K7 [(i8 & 15) as usize];
-            K65[i as usize]  = (K6 [(i8 >> 4) as usize] << 4) | K5 [(i8 & 15) as usize];
-            K43[i as usize]  = (K4 [(i8 >> 4) as usize] << 4) | K3 [(i8 & 15) as usize];
-            K21[i as usize]  = (K2 [(i8 >> 4) as usize] << 4) | K1 [(i8 & 15) as usize];
+            params.k175[i as usize] = (s[15][(i8 >> 4) as usize] << 4) | s[14][(i8 & 15) as usize];
+            params.k153[i as usize] = (s[13][(i8 >> 4) as usize] << 4) | s[12][(i8 & 15) as usize];
+            params.k131[i as usize] = (s[11][(i8 >> 4) as usize] << 4) | s[10][(i8 & 15) as usize];
+            params.k109[i as usize] = (s[9][(i8 >> 4) as usize]  << 4) | s[8][(i8 & 15) as usize];
+
+            params.k87[i as usize]  = (s[7][(i8 >> 4) as usize]  << 4) | s[6][(i8 & 15) as usize];
+            params.k65[i as usize]  = (s[5][(i8 >> 4) as usize]  << 4) | s[4][(i8 & 15) as usize];
+            params.k43[i as usize]  = (s[3][(i8 >> 4) as usize]  << 4) | s[2][(i8 & 15) as usize];
+            params.k21[i as usize]  = (s[1][(i8 >> 4) as usize]  << 4) | s[0][(i8 & 15) as usize];
         }
+
+        Ok(params)
+    }
+
+    /// The S-boxes this Rust port has always shipped as its default
+    /// (the id-GostR3411-94-TestParamSet tables from RFC 4357).
+    pub fn gost_r3411_94_test_paramset() -> Self {
+        Self::from_sboxes([
+            [0x4,0xA,0x9,0x2,0xD,0x8,0x0,0xE,0x6,0xB,0x1,0xC,0x7,0xF,0x5,0x3],
+            [0xE,0xB,0x4,0xC,0x6,0xD,0xF,0xA,0x2,0x3,0x8,0x1,0x0,0x7,0x5,0x9],
+            [0x5,0x8,0x1,0xD,0xA,0x3,0x4,0x2,0xE,0xF,0xC,0x7,0x6,0x0,0x9,0xB],
+            [0x7,0xD,0xA,0x1,0x0,0x8,0x9,0xF,0xE,0x4,0x6,0xC,0xB,0x2,0x5,0x3],
+            [0x6,0xC,0x7,0x1,0x5,0xF,0xD,0x8,0x4,0xA,0x9,0xE,0x0,0x3,0xB,0x2],
+            [0x4,0xB,0xA,0x0,0x7,0x2,0x1,0xD,0x3,0x6,0x8,0x5,0x9,0xC,0xF,0xE],
+            [0xD,0xB,0x4,0x1,0x3,0xF,0x5,0x9,0x0,0xA,0xE,0x7,0x6,0x8,0x2,0xC],
+            [0x1,0xF,0xD,0x0,0x5,0x7,0xA,0x4,0x9,0x2,0x3,0xE,0x6,0xB,0x8,0xC],
+            [0xC,0x4,0x6,0x2,0xA,0x5,0xB,0x9,0xE,0x8,0xD,0x7,0x0,0x3,0xF,0x1],
+            [0x6,0x8,0x2,0x3,0x9,0xA,0x5,0xC,0x1,0xE,0x4,0x7,0xB,0xD,0x0,0xF],
+            [0xB,0x3,0x5,0x8,0x2,0xF,0xA,0xD,0xE,0x1,0x7,0x4,0xC,0x9,0x6,0x0],
+            [0xC,0x8,0x2,0x1,0xD,0x4,0xF,0x6,0x7,0x0,0xA,0x5,0x3,0xE,0x9,0xB],
+            [0x7,0xF,0x5,0xA,0x8,0x1,0x6,0xD,0x0,0x9,0x3,0xE,0xB,0x4,0x2,0xC],
+            [0x5,0xD,0xF,0x6,0x9,0x2,0xC,0xA,0xB,0x7,0x8,0x1,0x4,0x3,0xE,0x0],
+            [0x8,0xE,0x2,0x5,0x6,0x9,0x1,0xC,0xF,0x4,0xB,0x0,0xD,0xA,0x3,0x7],
+            [0x1,0x7,0xE,0xD,0x0,0x5,0x8,0x3,0x4,0xF,0xA,0x6,0x9,0xC,0xB,0x2],
+        ]).expect("built-in test paramset tables are valid permutations")
+    }
+
+    /// The CryptoPro S-boxes from RFC 4357, for interop with the other
+    /// common GOST 28147-89 parameter set.
+    pub fn cryptopro_paramset() -> Self {
+        let box_a = [0x9,0x6,0x3,0x2,0x8,0xB,0x1,0x7,0xA,0x4,0xE,0xF,0xC,0x0,0xD,0x5];
+        let box_b = [0x3,0x7,0xE,0x9,0x8,0xA,0xF,0x0,0x5,0x2,0x6,0xC,0xB,0x4,0xD,0x1];
+        let box_c = [0xE,0x4,0x6,0x2,0xB,0x3,0xD,0x8,0xC,0xF,0x5,0xA,0x0,0x7,0x1,0x9];
+        let box_d = [0xE,0x7,0xA,0xC,0xD,0x1,0x3,0x9,0x0,0x2,0xB,0x4,0xF,0x8,0x5,0x6];
+        let box_e = [0xB,0x5,0x1,0x9,0x8,0xD,0xF,0x0,0xE,0x4,0x2,0x3,0xC,0x7,0xA,0x6];
+        let box_f = [0x3,0xA,0xD,0xC,0x1,0x2,0x0,0xB,0x7,0x5,0x9,0x4,0x8,0xF,0xE,0x6];
+        let box_g = [0x1,0xD,0x2,0x9,0x7,0xA,0x6,0x0,0x8,0xC,0x4,0x5,0xF,0x3,0xB,0xE];
+        let box_h = [0xB,0xA,0xF,0x5,0x0,0xC,0xE,0x8,0x6,0x2,0x3,0x9,0x1,0x7,0xD,0x4];
+        Self::from_sboxes([
+            box_a, box_b, box_c, box_d, box_e, box_f, box_g, box_h,
+            box_a, box_b, box_c, box_d, box_e, box_f, box_g, box_h,
+        ]).expect("built-in CryptoPro paramset tables are valid permutations")
+    }
+}
+
+impl Default for Gost2Params {
+    fn default() -> Self {
+        Self::gost_r3411_94_test_paramset()
     }
 }
 
@@ -215,64 +284,858 @@ fn kboxinit() {
 
 // The C version declares __inline__ f(). We'll keep it as a normal function.
 // Argument/return are u64; rotate left by 11 bits matches ((x<<11)|(x>>(64-11))).
-fn f(mut x: Word64) -> Word64 {
+fn f(params: &Gost2Params, mut x: Word64) -> Word64 {
     let mut y = x >> 32;
     let mut z = x & 0xFFFF_FFFF;
 
-    unsafe {
-        // Faster path using prebuilt byte tables (like the non-TEST branch)
-        y = ((K87[((y >> 24) & 0xFF) as usize] as Word64) << 24)
-          | ((K65[((y >> 16) & 0xFF) as usize] as Word64) << 16)
-          | ((K43[((y >> 8)  & 0xFF) as usize] as Word64) << 8)
-          |  (K21[( y        & 0xFF) as usize] as Word64);
+    // Faster path using prebuilt byte tables (like the non-TEST branch)
+    y = ((params.k87[((y >> 24) & 0xFF) as usize] as Word64) << 24)
+      | ((params.k65[((y >> 16) & 0xFF) as usize] as Word64) << 16)
+      | ((params.k43[((y >> 8)  & 0xFF) as usize] as Word64) << 8)
+      |  (params.k21[( y        & 0xFF) as usize] as Word64);
 
-        z = ((K175[((z >> 24) & 0xFF) as usize] as Word64) << 24)
-          | ((K153[((z >> 16) & 0xFF) as usize] as Word64) << 16)
-          | ((K131[((z >> 8)  & 0xFF) as usize] as Word64) << 8)
-          |  (K109[( z        & 0xFF) as usize] as Word64);
+    z = ((params.k175[((z >> 24) & 0xFF) as usize] as Word64) << 24)
+      | ((params.k153[((z >> 16) & 0xFF) as usize] as Word64) << 16)
+      | ((params.k131[((z >> 8)  & 0xFF) as usize] as Word64) << 8)
+      |  (params.k109[( z        & 0xFF) as usize] as Word64);
 
-        x = (y << 32) | (z & 0xFFFF_FFFF);
-    }
+    x = (y << 32) | (z & 0xFFFF_FFFF);
 
     // Rotate left 11 bits
     x.rotate_left(11)
 }
 
-fn gostcrypt(input: [Word64; 2], key: &[Word64; 64]) -> [Word64; 2] {
+fn gostcrypt(params: &Gost2Params, input: [Word64; 2], key: &[Word64; 64]) -> [Word64; 2] {
     let mut ngost1 = input[0];
     let mut ngost2 = input[1];
 
     let mut k = 0usize;
     for _ in 0..32 {
         // ngost2 ^= f(ngost1+key[k++]);
-        ngost2 ^= f(ngost1.wrapping_add(key[k]));
+        ngost2 ^= f(params, ngost1.wrapping_add(key[k]));
         k += 1;
         // ngost1 ^= f(ngost2+key[k++]);
-        ngost1 ^= f(ngost2.wrapping_add(key[k]));
+        ngost1 ^= f(params, ngost2.wrapping_add(key[k]));
         k += 1;
     }
 
     [ngost2, ngost1]
 }
 
-fn gostdecrypt(input: [Word64; 2], key: &[Word64; 64]) -> [Word64; 2] {
+fn gostdecrypt(params: &Gost2Params, input: [Word64; 2], key: &[Word64; 64]) -> [Word64; 2] {
     let mut ngost1 = input[0];
     let mut ngost2 = input[1];
 
     let mut k: isize = 63;
     for _ in 0..32 {
         // ngost2 ^= f(ngost1+key[k--]);
-        ngost2 ^= f(ngost1.wrapping_add(key[k as usize]));
+        ngost2 ^= f(params, ngost1.wrapping_add(key[k as usize]));
         k -= 1;
         // ngost1 ^= f(ngost2+key[k--]);
-        ngost1 ^= f(ngost2.wrapping_add(key[k as usize]));
+        ngost1 ^= f(params, ngost2.wrapping_add(key[k as usize]));
         k -= 1;
     }
 
     [ngost2, ngost1]
 }
 
+// --- RustCrypto `cipher` trait support ---
+// Implementing BlockSizeUser/KeyInit/BlockEncrypt/BlockDecrypt lets Gost2_128
+// be wrapped by the generic mode-of-operation crates (cbc, ctr, cfb, ...)
+// instead of only being driven through gostcrypt/gostdecrypt directly.
+
+use cipher::{
+    consts::{U16, U32},
+    BlockCipher, BlockEncrypt, Key, KeyInit, KeySizeUser,
+};
+
+/// GOST2-128 block cipher, with its 64 subkeys already expanded from a
+/// 32-byte key via the `Md2iiHasher`/`create_keys` pipeline.
+#[allow(non_camel_case_types)]
+pub struct Gost2_128 {
+    key: [Word64; 64],
+    params: Gost2Params,
+}
+
+impl Gost2_128 {
+    /// Like `KeyInit::new`, but with an explicit S-box choice instead of
+    /// the default `Gost2Params::gost_r3411_94_test_paramset()`.
+    pub fn with_params(key: &Key<Self>, params: Gost2Params) -> Self {
+        let mut hasher = Md2iiHasher::new();
+        hasher.update(key.as_slice());
+        let mut h4 = [0u8; N1];
+        hasher.finalize(&mut h4);
+        let mut subkeys = [0u64; 64];
+        create_keys(&h4, &mut subkeys);
+        Self { key: subkeys, params }
+    }
+}
+
+impl BlockCipher for Gost2_128 {}
+
+impl KeySizeUser for Gost2_128 {
+    type KeySize = U32;
+}
+
+impl KeyInit for Gost2_128 {
+    fn new(key: &Key<Self>) -> Self {
+        Self::with_params(key, Gost2Params::gost_r3411_94_test_paramset())
+    }
+}
+
+cipher::impl_simple_block_encdec!(
+    Gost2_128, U16, cipher, block,
+    encrypt: {
+        let bytes: [u8; 16] = block.clone_in().into();
+        let in0 = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let in1 = u64::from_be_bytes(bytes[8..].try_into().unwrap());
+        let out = gostcrypt(&cipher.params, [in0, in1], &cipher.key);
+        let mut out_bytes = [0u8; 16];
+        out_bytes[..8].copy_from_slice(&out[0].to_be_bytes());
+        out_bytes[8..].copy_from_slice(&out[1].to_be_bytes());
+        block.get_out().copy_from_slice(&out_bytes);
+    }
+    decrypt: {
+        let bytes: [u8; 16] = block.clone_in().into();
+        let in0 = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+        let in1 = u64::from_be_bytes(bytes[8..].try_into().unwrap());
+        let out = gostdecrypt(&cipher.params, [in0, in1], &cipher.key);
+        let mut out_bytes = [0u8; 16];
+        out_bytes[..8].copy_from_slice(&out[0].to_be_bytes());
+        out_bytes[8..].copy_from_slice(&out[1].to_be_bytes());
+        block.get_out().copy_from_slice(&out_bytes);
+    }
+);
+
+// --- OCB3 authenticated encryption ---
+// GOST2-128 is a true 128-bit block cipher, so we can build an OCB3-style
+// AEAD directly on top of gostcrypt/gostdecrypt and get confidentiality plus
+// integrity in a single pass, instead of layering a separate MAC over a
+// chaining mode.
+
+const OCB_NONCE_LEN: usize = 12;
+const OCB_TAG_LEN: usize = 16;
+
+fn xor16(a: &[u8; 16], b: &[u8; 16]) -> [u8; 16] {
+    let mut out = [0u8; 16];
+    for i in 0..16 {
+        out[i] = a[i] ^ b[i];
+    }
+    out
+}
+
+fn ocb_block_encrypt(params: &Gost2Params, key: &[Word64; 64], input: &[u8; 16]) -> [u8; 16] {
+    let in0 = u64::from_be_bytes(input[0..8].try_into().unwrap());
+    let in1 = u64::from_be_bytes(input[8..16].try_into().unwrap());
+    let out = gostcrypt(params, [in0, in1], key);
+    let mut b = [0u8; 16];
+    b[0..8].copy_from_slice(&out[0].to_be_bytes());
+    b[8..16].copy_from_slice(&out[1].to_be_bytes());
+    b
+}
+
+fn ocb_block_decrypt(params: &Gost2Params, key: &[Word64; 64], input: &[u8; 16]) -> [u8; 16] {
+    let in0 = u64::from_be_bytes(input[0..8].try_into().unwrap());
+    let in1 = u64::from_be_bytes(input[8..16].try_into().unwrap());
+    let out = gostdecrypt(params, [in0, in1], key);
+    let mut b = [0u8; 16];
+    b[0..8].copy_from_slice(&out[0].to_be_bytes());
+    b[8..16].copy_from_slice(&out[1].to_be_bytes());
+    b
+}
+
+/* GF(2^128) doubling: left shift the 128-bit big-endian value by one bit,
+ * and if the bit shifted out was set, XOR the low byte with the reduction
+ * constant 0x87. */
+fn double_block(v: [u8; 16]) -> [u8; 16] {
+    let carry = (v[0] & 0x80) != 0;
+    let mut out = [0u8; 16];
+    for i in 0..15 {
+        out[i] = (v[i] << 1) | (v[i + 1] >> 7);
+    }
+    out[15] = v[15] << 1;
+    if carry {
+        out[15] ^= 0x87;
+    }
+    out
+}
+
+fn ntz(i: u64) -> u32 {
+    i.trailing_zeros()
+}
+
+/* L_* = E_K(0), L_$ = double(L_*), L_0 = double(L_$), L_i = double(L_{i-1}).
+ * L_1, L_2, ... are grown on demand since the number of message blocks
+ * isn't known up front. */
+struct LTable {
+    l_star: [u8; 16],
+    l_dollar: [u8; 16],
+    l: Vec<[u8; 16]>,
+}
+
+impl LTable {
+    fn new(params: &Gost2Params, key: &[Word64; 64]) -> Self {
+        let l_star = ocb_block_encrypt(params, key, &[0u8; 16]);
+        let l_dollar = double_block(l_star);
+        let l0 = double_block(l_dollar);
+        LTable { l_star, l_dollar, l: vec![l0] }
+    }
+
+    fn get(&mut self, i: usize) -> [u8; 16] {
+        while self.l.len() <= i {
+            let next = double_block(*self.l.last().unwrap());
+            self.l.push(next);
+        }
+        self.l[i]
+    }
+}
+
+/* Derive the initial offset from the nonce via the OCB "stretch": format the
+ * nonce into a 128-bit value whose low 6 bits hold a bit position, encrypt
+ * the value with those 6 bits cleared, extend the result by 64 more bits,
+ * then take the 128-bit window starting at that bit position. */
+fn ocb_initial_offset(params: &Gost2Params, key: &[Word64; 64], nonce: &[u8; OCB_NONCE_LEN]) -> [u8; 16] {
+    let mut nonce_block = [0u8; 16];
+    nonce_block[3] = 0x01;
+    nonce_block[4..16].copy_from_slice(nonce);
+    let bottom = (nonce_block[15] & 0x3f) as u32;
+
+    let mut ktop_input = nonce_block;
+    ktop_input[15] &= 0xc0;
+    let ktop = ocb_block_encrypt(params, key, &ktop_input);
+
+    let mut stretch = [0u8; 24];
+    stretch[0..16].copy_from_slice(&ktop);
+    for i in 0..8 {
+        stretch[16 + i] = ktop[i] ^ ktop[i + 1];
+    }
+
+    let byte_off = (bottom / 8) as usize;
+    let bit_off = bottom % 8;
+    let mut offset = [0u8; 16];
+    for i in 0..16 {
+        let b0 = stretch[byte_off + i];
+        let b1 = if byte_off + i + 1 < 24 { stretch[byte_off + i + 1] } else { 0 };
+        offset[i] = if bit_off == 0 { b0 } else { (b0 << bit_off) | (b1 >> (8 - bit_off)) };
+    }
+    offset
+}
+
+/* HASH(AD): same offset recurrence as the message, seeded from an all-zero
+ * offset rather than the nonce-derived one. */
+fn ocb_hash_aad(params: &Gost2Params, key: &[Word64; 64], ltable: &mut LTable, aad: &[u8]) -> [u8; 16] {
+    let mut offset = [0u8; 16];
+    let mut sum = [0u8; 16];
+    let full_blocks = aad.len() / 16;
+    for i in 0..full_blocks {
+        let l = ltable.get(ntz((i + 1) as u64) as usize);
+        offset = xor16(&offset, &l);
+        let mut a = [0u8; 16];
+        a.copy_from_slice(&aad[i * 16..i * 16 + 16]);
+        sum = xor16(&sum, &ocb_block_encrypt(params, key, &xor16(&a, &offset)));
+    }
+    let rem = &aad[full_blocks * 16..];
+    if !rem.is_empty() {
+        offset = xor16(&offset, &ltable.l_star);
+        let mut padded = [0u8; 16];
+        padded[..rem.len()].copy_from_slice(rem);
+        padded[rem.len()] = 0x80;
+        sum = xor16(&sum, &ocb_block_encrypt(params, key, &xor16(&padded, &offset)));
+    }
+    sum
+}
+
+/* Constant-time tag comparison, so a forged ciphertext can't be tweaked one
+ * byte at a time against a timing oracle. */
+fn ct_eq16(a: &[u8; 16], b: &[u8; 16]) -> bool {
+    let mut diff = 0u8;
+    for i in 0..16 {
+        diff |= a[i] ^ b[i];
+    }
+    diff == 0
+}
+
+fn ocb_encrypt(params: &Gost2Params, key: &[Word64; 64], nonce: &[u8; OCB_NONCE_LEN], aad: &[u8], pt: &[u8]) -> (Vec<u8>, [u8; OCB_TAG_LEN]) {
+    let mut ltable = LTable::new(params, key);
+    let mut offset = ocb_initial_offset(params, key, nonce);
+    let mut checksum = [0u8; 16];
+    let mut ct = Vec::with_capacity(pt.len());
+
+    let full_blocks = pt.len() / 16;
+    for i in 0..full_blocks {
+        let l = ltable.get(ntz((i + 1) as u64) as usize);
+        offset = xor16(&offset, &l);
+        let mut p = [0u8; 16];
+        p.copy_from_slice(&pt[i * 16..i * 16 + 16]);
+        let c = xor16(&ocb_block_encrypt(params, key, &xor16(&p, &offset)), &offset);
+        ct.extend_from_slice(&c);
+        checksum = xor16(&checksum, &p);
+    }
+
+    let rem = &pt[full_blocks * 16..];
+    if !rem.is_empty() {
+        offset = xor16(&offset, &ltable.l_star);
+        let pad = ocb_block_encrypt(params, key, &offset);
+        let mut c = vec![0u8; rem.len()];
+        for j in 0..rem.len() {
+            c[j] = rem[j] ^ pad[j];
+        }
+        ct.extend_from_slice(&c);
+        let mut padded_p = [0u8; 16];
+        padded_p[..rem.len()].copy_from_slice(rem);
+        padded_p[rem.len()] = 0x80;
+        checksum = xor16(&checksum, &padded_p);
+    }
+
+    let tag_block = xor16(&xor16(&checksum, &offset), &ltable.l_dollar);
+    let tag_enc = ocb_block_encrypt(params, key, &tag_block);
+    let aad_hash = ocb_hash_aad(params, key, &mut ltable, aad);
+    (ct, xor16(&tag_enc, &aad_hash))
+}
+
+fn ocb_decrypt(params: &Gost2Params, key: &[Word64; 64], nonce: &[u8; OCB_NONCE_LEN], aad: &[u8], ct: &[u8], tag: &[u8; OCB_TAG_LEN]) -> Option<Vec<u8>> {
+    let mut ltable = LTable::new(params, key);
+    let mut offset = ocb_initial_offset(params, key, nonce);
+    let mut checksum = [0u8; 16];
+    let mut pt = Vec::with_capacity(ct.len());
+
+    let full_blocks = ct.len() / 16;
+    for i in 0..full_blocks {
+        let l = ltable.get(ntz((i + 1) as u64) as usize);
+        offset = xor16(&offset, &l);
+        let mut c = [0u8; 16];
+        c.copy_from_slice(&ct[i * 16..i * 16 + 16]);
+        let p = xor16(&ocb_block_decrypt(params, key, &xor16(&c, &offset)), &offset);
+        pt.extend_from_slice(&p);
+        checksum = xor16(&checksum, &p);
+    }
+
+    let rem = &ct[full_blocks * 16..];
+    if !rem.is_empty() {
+        offset = xor16(&offset, &ltable.l_star);
+        let pad = ocb_block_encrypt(params, key, &offset);
+        let mut p = vec![0u8; rem.len()];
+        for j in 0..rem.len() {
+            p[j] = rem[j] ^ pad[j];
+        }
+        pt.extend_from_slice(&p);
+        let mut padded_p = [0u8; 16];
+        padded_p[..rem.len()].copy_from_slice(&p);
+        padded_p[rem.len()] = 0x80;
+        checksum = xor16(&checksum, &padded_p);
+    }
+
+    let tag_block = xor16(&xor16(&checksum, &offset), &ltable.l_dollar);
+    let tag_enc = ocb_block_encrypt(params, key, &tag_block);
+    let aad_hash = ocb_hash_aad(params, key, &mut ltable, aad);
+    let expected_tag = xor16(&tag_enc, &aad_hash);
+
+    if ct_eq16(&expected_tag, tag) {
+        Some(pt)
+    } else {
+        None
+    }
+}
+
+/// OCB3-style AEAD wrapper around GOST2-128: one pass gives both
+/// confidentiality and integrity, with a 16-byte tag.
+pub struct Gost2_128Ocb {
+    key: [Word64; 64],
+    params: Gost2Params,
+}
+
+impl Gost2_128Ocb {
+    pub fn new(key: [Word64; 64]) -> Self {
+        Self::with_params(key, Gost2Params::gost_r3411_94_test_paramset())
+    }
+
+    pub fn with_params(key: [Word64; 64], params: Gost2Params) -> Self {
+        Self { key, params }
+    }
+
+    pub fn encrypt(&self, nonce: &[u8; OCB_NONCE_LEN], aad: &[u8], pt: &[u8]) -> (Vec<u8>, [u8; OCB_TAG_LEN]) {
+        ocb_encrypt(&self.params, &self.key, nonce, aad, pt)
+    }
+
+    pub fn decrypt(&self, nonce: &[u8; OCB_NONCE_LEN], aad: &[u8], ct: &[u8], tag: &[u8; OCB_TAG_LEN]) -> Option<Vec<u8>> {
+        ocb_decrypt(&self.params, &self.key, nonce, aad, ct, tag)
+    }
+}
+
+// --- Parallel CTR-mode file encryption ---
+// Now that Gost2_128 and Md2iiHasher keep all their state as owned fields
+// instead of `static mut` globals, a file's bytes can be split into
+// independent regions, each encrypted by its own Gost2_128 instance on its
+// own thread, with no shared mutable state between them.
+
+use std::fs;
+use std::io;
+use std::thread;
+
+const CTR_BLOCK_LEN: usize = 16;
+
+/// XOR `data` in place with the CTR-mode keystream, treating `data` as
+/// starting at block index `first_block_index` of the stream (block 0 is
+/// `cipher.encrypt_block` of the all-zero counter, block 1 the counter
+/// incremented by one, and so on).
+fn ctr_xor_region(cipher: &Gost2_128, first_block_index: u64, data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(CTR_BLOCK_LEN).enumerate() {
+        let counter = first_block_index + i as u64;
+        let mut block_bytes = [0u8; CTR_BLOCK_LEN];
+        block_bytes[8..].copy_from_slice(&counter.to_be_bytes());
+        let mut block = cipher::Block::<Gost2_128>::from(block_bytes);
+        cipher.encrypt_block(&mut block);
+        for (b, k) in chunk.iter_mut().zip(block.iter()) {
+            *b ^= k;
+        }
+    }
+}
+
+/// Encrypt or decrypt `data` in place with GOST2-128 in CTR mode (CTR is its
+/// own inverse), splitting the buffer into up to `num_threads` block-aligned
+/// regions and running one thread per region. Each thread builds its own
+/// `Gost2_128` instance from `key`/`params`, so the only thing shared across
+/// threads is the (read-only, `Copy`) key material.
+pub fn ctr_apply_parallel(key: &Key<Gost2_128>, params: Gost2Params, data: &mut [u8], num_threads: usize) {
+    let num_threads = num_threads.max(1);
+    let total_blocks = data.len().div_ceil(CTR_BLOCK_LEN);
+    let blocks_per_region = total_blocks.div_ceil(num_threads).max(1);
+    let region_bytes = blocks_per_region * CTR_BLOCK_LEN;
+
+    thread::scope(|scope| {
+        for (i, region) in data.chunks_mut(region_bytes).enumerate() {
+            let first_block_index = (i * blocks_per_region) as u64;
+            scope.spawn(move || {
+                let cipher = Gost2_128::with_params(key, params);
+                ctr_xor_region(&cipher, first_block_index, region);
+            });
+        }
+    });
+}
+
+/// Encrypt `infile` into `outfile` with GOST2-128 in CTR mode, splitting the
+/// work across `num_threads` threads.
+pub fn encrypt_file_parallel(infile: &str, outfile: &str, key: &Key<Gost2_128>, params: Gost2Params, num_threads: usize) -> io::Result<()> {
+    let mut data = fs::read(infile)?;
+    ctr_apply_parallel(key, params, &mut data, num_threads);
+    fs::write(outfile, data)
+}
+
+/// Decrypt `infile` (as produced by `encrypt_file_parallel` with the same
+/// key/params) into `outfile`. CTR mode is its own inverse, so this is the
+/// same transform as encryption.
+pub fn decrypt_file_parallel(infile: &str, outfile: &str, key: &Key<Gost2_128>, params: Gost2Params, num_threads: usize) -> io::Result<()> {
+    encrypt_file_parallel(infile, outfile, key, params, num_threads)
+}
+
+/* ---------------------- Portable secure random ----------------------
+ * We follow the C code preference order:
+ *   - arc4random_buf (BSD/macOS)
+ *   - /dev/urandom (Unix)
+ *   - BCryptGenRandom (Windows)
+ *   - fallback weak RNG (time-based)
+ */
+
+mod rng {
+    #[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))]
+    pub fn secure_random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+        // Use arc4random_buf via FFI
+        extern "C" { fn arc4random_buf(buf: *mut core::ffi::c_void, len: usize); }
+        unsafe { arc4random_buf(buf.as_mut_ptr() as *mut _, buf.len()); }
+        Ok(())
+    }
+
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd", target_os = "netbsd"))))]
+    pub fn secure_random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+        // Read from /dev/urandom
+        use std::fs::File;
+        use std::io::Read;
+        let mut f = File::open("/dev/urandom")?;
+        f.read_exact(buf)?;
+        Ok(())
+    }
+
+    #[cfg(windows)]
+    pub fn secure_random_bytes(buf: &mut [u8]) -> std::io::Result<()> {
+        // BCryptGenRandom from CNG
+        type NTSTATUS = i32;
+        const STATUS_SUCCESS: NTSTATUS = 0;
+        const BCRYPT_USE_SYSTEM_PREFERRED_RNG: u32 = 0x00000002;
+
+        #[link(name = "bcrypt")]
+        extern "system" {
+            fn BCryptGenRandom(
+                hAlgorithm: *mut core::ffi::c_void,
+                pbBuffer: *mut u8,
+                cbBuffer: u32,
+                dwFlags: u32,
+            ) -> NTSTATUS;
+        }
+        let st = unsafe {
+            BCryptGenRandom(
+                std::ptr::null_mut(),
+                buf.as_mut_ptr(),
+                buf.len() as u32,
+                BCRYPT_USE_SYSTEM_PREFERRED_RNG,
+            )
+        };
+        if st == STATUS_SUCCESS { Ok(()) } else { Err(std::io::Error::new(std::io::ErrorKind::Other, "BCryptGenRandom failed")) }
+    }
+
+    // Last-resort weak RNG (only if all above fail, explicitly requested)
+    pub fn fallback_weak_rng(buf: &mut [u8]) {
+        /* WARNING: This is NOT cryptographically secure. */
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos();
+        // simple xorshift64* seeded from time
+        fn xorshift64(mut x: u64) -> u64 {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            x
+        }
+        let mut seed = (now as u64) ^ 0x9E3779B97F4A7C15u64;
+        for b in buf.iter_mut() {
+            seed = xorshift64(seed);
+            *b = (seed & 0xFF) as u8;
+        }
+    }
+
+    pub fn get_random(buf: &mut [u8]) {
+        if secure_random_bytes(buf).is_ok() {
+            return;
+        }
+        eprintln!("WARNING: secure RNG unavailable; using weak time-based fallback.");
+        fallback_weak_rng(buf);
+    }
+}
+
+// --- Streaming CTR file format with versioned header ---
+// The demos in `main` only show raw single-block calls; this is the actual
+// file-encryption subsystem the crate is named for. A file is a small
+// header (magic byte, version byte, per-file salt, per-file nonce, KDF
+// iteration count) followed
+// by the plaintext XORed with a CTR keystream, read and written through a
+// fixed-size buffer so files far larger than memory can be processed.
+
+use std::fs::File;
+use std::io::{Read, Write, BufReader, BufWriter};
+
+const FILE_MAGIC: u8 = 0xC7;
+const FILE_VERSION: u8 = 2;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 16;
+const ITERATIONS_LEN: usize = 4;
+const HEADER_LEN: usize = 2 + SALT_LEN + NONCE_LEN + ITERATIONS_LEN;
+const STREAM_BUF_LEN: usize = 64 * 1024; // a multiple of CTR_BLOCK_LEN
+
+/// Default work factor for [`derive_subkeys`] when the caller doesn't pick
+/// their own. Tens of thousands of MD2II rounds costs a fraction of a
+/// second for a legitimate user but meaningfully slows down brute force.
+pub const DEFAULT_KDF_ITERATIONS: u32 = 20_000;
+
+/// Iterated, salted KDF built on top of the MD2II primitive: the password
+/// is no longer truncated to exactly 32 bytes, and each round's `h4` output
+/// is folded back in as the next round's input, so deriving the subkeys
+/// costs `iterations` MD2II passes instead of one.
+fn derive_subkeys(password: &str, salt: &[u8; SALT_LEN], iterations: u32) -> [Word64; 64] {
+    let mut h4 = [0u8; N1];
+
+    let mut hasher = Md2iiHasher::new();
+    hasher.update(salt);
+    hasher.update(password.as_bytes());
+    hasher.finalize(&mut h4);
+
+    for _ in 1..iterations.max(1) {
+        let mut hasher = Md2iiHasher::new();
+        hasher.update(&h4);
+        hasher.update(salt);
+        hasher.update(password.as_bytes());
+        hasher.finalize(&mut h4);
+    }
+
+    let mut subkeys = [0u64; 64];
+    create_keys(&h4, &mut subkeys);
+    subkeys
+}
+
+/// keystream block j = E_K(nonce ⊕ j), with j as a big-endian 128-bit counter.
+fn ctr_keystream_block(params: &Gost2Params, subkeys: &[Word64; 64], nonce: &[u8; NONCE_LEN], block_index: u64) -> [u8; CTR_BLOCK_LEN] {
+    let mut counter_bytes = [0u8; NONCE_LEN];
+    counter_bytes[8..].copy_from_slice(&block_index.to_be_bytes());
+    let mut input = [0u8; NONCE_LEN];
+    for i in 0..NONCE_LEN {
+        input[i] = nonce[i] ^ counter_bytes[i];
+    }
+    let in0 = u64::from_be_bytes(input[0..8].try_into().unwrap());
+    let in1 = u64::from_be_bytes(input[8..16].try_into().unwrap());
+    let out = gostcrypt(params, [in0, in1], subkeys);
+    let mut ks = [0u8; CTR_BLOCK_LEN];
+    ks[0..8].copy_from_slice(&out[0].to_be_bytes());
+    ks[8..16].copy_from_slice(&out[1].to_be_bytes());
+    ks
+}
+
+/// Read into `buf` until it is full or the reader is exhausted, unlike a
+/// single `Read::read` call which may return fewer bytes than requested
+/// even before EOF.
+fn fill_buffer<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        let n = reader.read(&mut buf[total..])?;
+        if n == 0 {
+            break;
+        }
+        total += n;
+    }
+    Ok(total)
+}
+
+/// CTR-mode stream transform shared by `encrypt_file`/`decrypt_file`: XOR is
+/// its own inverse, so encryption and decryption read the same way, the
+/// only difference being whether the header is written or read.
+fn ctr_stream(params: &Gost2Params, subkeys: &[Word64; 64], nonce: &[u8; NONCE_LEN], reader: &mut impl Read, writer: &mut impl Write) -> io::Result<()> {
+    let mut buf = [0u8; STREAM_BUF_LEN];
+    let mut block_index: u64 = 0;
+    loop {
+        let n = fill_buffer(reader, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let chunk = &mut buf[..n];
+        for (i, block) in chunk.chunks_mut(CTR_BLOCK_LEN).enumerate() {
+            let ks = ctr_keystream_block(params, subkeys, nonce, block_index + i as u64);
+            for (b, k) in block.iter_mut().zip(ks.iter()) {
+                *b ^= k;
+            }
+        }
+        block_index += chunk.len().div_ceil(CTR_BLOCK_LEN) as u64;
+        writer.write_all(chunk)?;
+        if n < buf.len() {
+            break;
+        }
+    }
+    writer.flush()
+}
+
+/// Encrypt `path_in` into `path_out` using [`DEFAULT_KDF_ITERATIONS`] rounds
+/// of the password KDF. See [`encrypt_file_with_iterations`] to pick a
+/// different work factor.
+pub fn encrypt_file(path_in: &str, path_out: &str, password: &str) -> io::Result<()> {
+    encrypt_file_with_iterations(path_in, path_out, password, DEFAULT_KDF_ITERATIONS)
+}
+
+/// Encrypt `path_in` into `path_out`: a fresh random salt and nonce are
+/// generated and stored in the header alongside `iterations`, then the body
+/// is streamed through GOST2-128 in CTR mode.
+pub fn encrypt_file_with_iterations(path_in: &str, path_out: &str, password: &str, iterations: u32) -> io::Result<()> {
+    let params = Gost2Params::gost_r3411_94_test_paramset();
+
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rng::get_random(&mut salt);
+    rng::get_random(&mut nonce);
+    let subkeys = derive_subkeys(password, &salt, iterations);
+
+    let mut reader = BufReader::new(File::open(path_in)?);
+    let mut writer = BufWriter::new(File::create(path_out)?);
+
+    writer.write_all(&[FILE_MAGIC, FILE_VERSION])?;
+    writer.write_all(&salt)?;
+    writer.write_all(&nonce)?;
+    writer.write_all(&iterations.to_be_bytes())?;
+
+    ctr_stream(&params, &subkeys, &nonce, &mut reader, &mut writer)
+}
+
+/// Decrypt `path_in` (as produced by `encrypt_file`/`encrypt_file_with_iterations`
+/// with the same password) into `path_out`. The salt and iteration count are
+/// read back from the header, so the KDF reproduces the same subkeys.
+pub fn decrypt_file(path_in: &str, path_out: &str, password: &str) -> io::Result<()> {
+    let params = Gost2Params::gost_r3411_94_test_paramset();
+
+    let mut reader = BufReader::new(File::open(path_in)?);
+    let mut header = [0u8; HEADER_LEN];
+    reader.read_exact(&mut header)?;
+    if header[0] != FILE_MAGIC || header[1] != FILE_VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a recognized GOST2-128 CTR file"));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&header[2..2 + SALT_LEN]);
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&header[2 + SALT_LEN..2 + SALT_LEN + NONCE_LEN]);
+    let iterations = u32::from_be_bytes(header[2 + SALT_LEN + NONCE_LEN..].try_into().unwrap());
+
+    let subkeys = derive_subkeys(password, &salt, iterations);
+
+    let mut writer = BufWriter::new(File::create(path_out)?);
+    ctr_stream(&params, &subkeys, &nonce, &mut reader, &mut writer)
+}
+
+// --- Hybrid post-quantum key wrapping ---
+// An alternative to the password KDF above: the 4096-bit subkey seed is
+// chosen at random and transported to the recipient under a Kyber KEM
+// public key instead of being reconstructed from a password. The KEM only
+// agrees on a shared secret, not arbitrary data, so the seed itself is
+// masked by XORing it with that shared secret before storage; only the
+// holder of the matching Kyber secret key can decapsulate the shared
+// secret and unmask it. An optional Dilithium signature over the file
+// header lets a recipient verify who wrapped the key before trusting the
+// ciphertext body.
+
+use pqcrypto_kyber::kyber768;
+use pqcrypto_dilithium::dilithium3;
+use pqcrypto_traits::kem::{Ciphertext as KemCiphertext, SharedSecret as KemSharedSecret};
+use pqcrypto_traits::sign::DetachedSignature as SignDetachedSignature;
+
+const PQ_SEED_LEN: usize = 32;
+
+/// A [`wrap_key`] output meant to be embedded in a file header: the Kyber768
+/// KEM ciphertext plus the subkey seed, masked by XORing it with the
+/// encapsulated shared secret.
+pub struct WrappedSubkeySeed {
+    pub kem_ciphertext: Vec<u8>,
+    pub masked_seed: [u8; PQ_SEED_LEN],
+}
+
+/// Generate a random subkey seed, encapsulate a shared secret to
+/// `recipient_pk` and use it to mask the seed, then derive the 64 GOST2-128
+/// subkeys from the seed through the same [`Md2iiHasher`] `update`/`finalize`
+/// + [`create_keys`] pipeline used everywhere else in this file.
+///
+/// Returns the subkeys, ready for bulk encryption, alongside the wrapped
+/// seed to store in the header; [`unwrap_key`] is the inverse.
+pub fn wrap_key(recipient_pk: &kyber768::PublicKey) -> ([Word64; 64], WrappedSubkeySeed) {
+    let mut seed = [0u8; PQ_SEED_LEN];
+    rng::get_random(&mut seed);
+
+    let (shared_secret, kem_ciphertext) = kyber768::encapsulate(recipient_pk);
+    let mut masked_seed = [0u8; PQ_SEED_LEN];
+    for i in 0..PQ_SEED_LEN {
+        masked_seed[i] = seed[i] ^ shared_secret.as_bytes()[i];
+    }
+
+    let subkeys = subkeys_from_seed(&seed);
+
+    (subkeys, WrappedSubkeySeed { kem_ciphertext: kem_ciphertext.as_bytes().to_vec(), masked_seed })
+}
+
+/// Inverse of [`wrap_key`]: decapsulate `wrapped.kem_ciphertext` with
+/// `recipient_sk` to recover the shared secret, unmask the seed, and rebuild
+/// the identical 64-subkey schedule.
+pub fn unwrap_key(recipient_sk: &kyber768::SecretKey, wrapped: &WrappedSubkeySeed) -> [Word64; 64] {
+    let kem_ciphertext = kyber768::Ciphertext::from_bytes(&wrapped.kem_ciphertext)
+        .expect("malformed Kyber768 ciphertext in header");
+    let shared_secret = kyber768::decapsulate(&kem_ciphertext, recipient_sk);
+
+    let mut seed = [0u8; PQ_SEED_LEN];
+    for i in 0..PQ_SEED_LEN {
+        seed[i] = wrapped.masked_seed[i] ^ shared_secret.as_bytes()[i];
+    }
+
+    subkeys_from_seed(&seed)
+}
+
+/// Shared `seed -> h4 -> subkeys` step used by both [`wrap_key`] and
+/// [`unwrap_key`], so the two sides always reconstruct the same schedule.
+fn subkeys_from_seed(seed: &[u8; PQ_SEED_LEN]) -> [Word64; 64] {
+    let mut h4 = [0u8; N1];
+    let mut hasher = Md2iiHasher::new();
+    hasher.update(seed);
+    hasher.finalize(&mut h4);
+    let mut subkeys = [0u64; 64];
+    create_keys(&h4, &mut subkeys);
+    subkeys
+}
+
+/// Sign a file header with Dilithium3, so a recipient can call
+/// [`verify_header`] to authenticate the sender before trusting the body.
+pub fn sign_header(signer_sk: &dilithium3::SecretKey, header: &[u8]) -> Vec<u8> {
+    dilithium3::detached_sign(header, signer_sk).as_bytes().to_vec()
+}
+
+/// Verify a header signature produced by [`sign_header`]. Returns `false`
+/// for a malformed signature as well as a genuinely invalid one.
+pub fn verify_header(signer_pk: &dilithium3::PublicKey, header: &[u8], signature: &[u8]) -> bool {
+    match dilithium3::DetachedSignature::from_bytes(signature) {
+        Ok(sig) => dilithium3::verify_detached_signature(&sig, header, signer_pk).is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn usage(prog: &str) {
+    eprintln!("Usage: {} [encrypt-file|decrypt-file <input_file> <output_file> <password>]", prog);
+    eprintln!("       {} [encrypt-parallel|decrypt-parallel <input_file> <output_file> <64-hex-char key> [num_threads]]", prog);
+    eprintln!("  With no arguments, runs the built-in subkey/cipher demo instead.");
+}
+
+/// Parse a 64-character hex string into the 32-byte key `ctr_apply_parallel`
+/// expects, for the `encrypt-parallel`/`decrypt-parallel` CLI subcommands.
+fn parse_hex_key32(s: &str) -> io::Result<[u8; 32]> {
+    if s.len() != 64 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "key must be exactly 64 hex characters (32 bytes)"));
+    }
+    let mut out = [0u8; 32];
+    for i in 0..32 {
+        out[i] = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "key must be valid hex"))?;
+    }
+    Ok(out)
+}
+
 fn main() {
+    // Streaming CTR file codec (see `encrypt_file`/`decrypt_file` above) is a
+    // library entry point with no CLI of its own; expose it here behind an
+    // explicit subcommand so it's reachable without disturbing the default
+    // (argument-less) demo run below.
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 {
+        match args[1].as_str() {
+            "encrypt-file" if args.len() == 5 => {
+                if let Err(e) = encrypt_file(&args[2], &args[3], &args[4]) {
+                    eprintln!("encrypt-file error: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            "decrypt-file" if args.len() == 5 => {
+                if let Err(e) = decrypt_file(&args[2], &args[3], &args[4]) {
+                    eprintln!("decrypt-file error: {}", e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            "encrypt-parallel" | "decrypt-parallel" if args.len() == 5 || args.len() == 6 => {
+                let key_bytes = parse_hex_key32(&args[4]).unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(2);
+                });
+                let key = *Key::<Gost2_128>::from_slice(&key_bytes);
+                let num_threads = args.get(5)
+                    .map(|s| s.parse().unwrap_or_else(|_| {
+                        usage(&args[0]);
+                        std::process::exit(2);
+                    }))
+                    .unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+                let params = Gost2Params::gost_r3411_94_test_paramset();
+                let result = if args[1] == "encrypt-parallel" {
+                    encrypt_file_parallel(&args[2], &args[3], &key, params, num_threads)
+                } else {
+                    decrypt_file_parallel(&args[2], &args[3], &key, params, num_threads)
+                };
+                if let Err(e) = result {
+                    eprintln!("{} error: {}", args[1], e);
+                    std::process::exit(1);
+                }
+                return;
+            }
+            "encrypt-file" | "decrypt-file" | "encrypt-parallel" | "decrypt-parallel" => {
+                usage(&args[0]);
+                std::process::exit(2);
+            }
+            _ => {}
+        }
+    }
+
     // unsigned char text[33]; /* up to 256 chars for the password */
     //                             /* password can be hexadecimal */
     // In Rust we use &str literals; we pass exactly 32 bytes to hashing, like C.
@@ -283,7 +1146,7 @@ fn main() {
 
     let mut h4 = [0u8; N1];
 
-    kboxinit();
+    let params = Gost2Params::gost_r3411_94_test_paramset();
 
     println!("GOST2-128 by Alexander PUKALL 2016 \n 128-bit block 4096-bit subkeys 64 rounds");
     println!("Code can be freely use even for commercial software");
@@ -292,19 +1155,19 @@ fn main() {
     /* The key creation procedure is slow, it only needs to be done once */
     /* as long as the user does not change the key. You can encrypt and decrypt */
     /* as many blocks as you want without having to hash the key again. */
-    /* kboxinit(); -> only once */
-    /* init(); hashing(text,length);  end(h4); -> only once */
+    /* Gost2Params::gost_r3411_94_test_paramset(); -> only once */
+    /* Md2iiHasher::new(); hasher.update(text); hasher.finalize(h4); -> only once */
     /* create_keys(h4,key); -> only once too */
 
     /* EXAMPLE 1 */
 
-    init();
+    let mut hasher = Md2iiHasher::new();
 
     let text1 = "My secret password!0123456789abc";
     // hashing(text, 32);
-    hashing(&text1.as_bytes()[..32], 32);
+    hasher.update(&text1.as_bytes()[..32]);
     // end(h4); /* h4 = 4096-bit key from hash "My secret password!0123456789abc */
-    end_fn(&mut h4);
+    hasher.finalize(&mut h4);
     // create_keys(h4,key); /* create 64 * 64-bit subkeys from h4 hash */
     create_keys(&h4, &mut key);
 
@@ -315,22 +1178,22 @@ fn main() {
     println!("Key 1:{}", text1);
     println!("Plaintext  1: {:016X}{:016X}", plain[0], plain[1]);
 
-    cipher = gostcrypt(plain, &key);
+    cipher = gostcrypt(&params, plain, &key);
 
     println!("Encryption 1: {:016X}{:016X}", cipher[0], cipher[1]);
 
-    decrypted = gostdecrypt(cipher, &key);
+    decrypted = gostdecrypt(&params, cipher, &key);
 
     println!("Decryption 1: {:016X}{:016X}\n", decrypted[0], decrypted[1]);
 
     /* EXAMPLE 2 */
 
-    init();
+    let mut hasher = Md2iiHasher::new();
 
     let text2 = "My secret password!0123456789ABC";
 
-    hashing(&text2.as_bytes()[..32], 32);
-    end_fn(&mut h4); /* h4 = 4096-bit key from hash "My secret password!0123456789ABC */
+    hasher.update(&text2.as_bytes()[..32]);
+    hasher.finalize(&mut h4); /* h4 = 4096-bit key from hash "My secret password!0123456789ABC */
     create_keys(&h4, &mut key); /* create 64 * 64-bit subkeys from h4 hash */
 
     // 0x000... block
@@ -339,22 +1202,22 @@ fn main() {
     println!("Key 2:{}", text2);
     println!("Plaintext  2: {:016X}{:016X}", plain[0], plain[1]);
 
-    cipher = gostcrypt(plain, &key);
+    cipher = gostcrypt(&params, plain, &key);
 
     println!("Encryption 2: {:016X}{:016X}", cipher[0], cipher[1]);
 
-    decrypted = gostdecrypt(cipher, &key);
+    decrypted = gostdecrypt(&params, cipher, &key);
 
     println!("Decryption 2: {:016X}{:016X}\n", decrypted[0], decrypted[1]);
 
     /* EXAMPLE 3 */
 
-    init();
+    let mut hasher = Md2iiHasher::new();
 
     let text3 = "My secret password!0123456789abZ";
 
-    hashing(&text3.as_bytes()[..32], 32);
-    end_fn(&mut h4); /* h4 = 4096-bit key from hash "My secret password!0123456789abZ */
+    hasher.update(&text3.as_bytes()[..32]);
+    hasher.finalize(&mut h4); /* h4 = 4096-bit key from hash "My secret password!0123456789abZ */
     create_keys(&h4, &mut key); /* create 64 * 64-bit subkeys from h4 hash */
 
     // 0x...0001 block
@@ -363,15 +1226,102 @@ fn main() {
     println!("Key 3:{}", text3);
     println!("Plaintext  3: {:016X}{:016X}", plain[0], plain[1]);
 
-    cipher = gostcrypt(plain, &key);
+    cipher = gostcrypt(&params, plain, &key);
 
     println!("Encryption 3: {:016X}{:016X}", cipher[0], cipher[1]);
 
-    decrypted = gostdecrypt(cipher, &key);
+    decrypted = gostdecrypt(&params, cipher, &key);
 
     println!("Decryption 3: {:016X}{:016X}\n", decrypted[0], decrypted[1]);
 }
 
+#[cfg(test)]
+mod aead_kdf_tests {
+    use super::*;
+
+    fn test_key() -> [Word64; 64] {
+        let mut hasher = Md2iiHasher::new();
+        hasher.update(&"My secret password!0123456789abc".as_bytes()[..32]);
+        let mut h4 = [0u8; N1];
+        hasher.finalize(&mut h4);
+        let mut key = [0u64; 64];
+        create_keys(&h4, &mut key);
+        key
+    }
+
+    #[test]
+    fn ocb3_round_trips_and_rejects_tampering() {
+        let ocb = Gost2_128Ocb::new(test_key());
+        let nonce = [0x11u8; OCB_NONCE_LEN];
+        let aad = b"header";
+        let pt = b"OCB3 round-trip test message, longer than one block!";
+
+        let (ct, tag) = ocb.encrypt(&nonce, aad, pt);
+        let recovered = ocb.decrypt(&nonce, aad, &ct, &tag).expect("valid tag must decrypt");
+        assert_eq!(recovered, pt);
+
+        let mut bad_ct = ct.clone();
+        bad_ct[0] ^= 1;
+        assert!(ocb.decrypt(&nonce, aad, &bad_ct, &tag).is_none(), "tampered ciphertext must not verify");
+
+        let mut bad_tag = tag;
+        bad_tag[0] ^= 1;
+        assert!(ocb.decrypt(&nonce, aad, &ct, &bad_tag).is_none(), "tampered tag must not verify");
+
+        assert!(ocb.decrypt(&nonce, b"wrong aad", &ct, &tag).is_none(), "mismatched AAD must not verify");
+    }
+
+    #[test]
+    fn ocb3_empty_plaintext_round_trips() {
+        let ocb = Gost2_128Ocb::new(test_key());
+        let nonce = [0x22u8; OCB_NONCE_LEN];
+        let (ct, tag) = ocb.encrypt(&nonce, b"", b"");
+        assert!(ct.is_empty());
+        assert_eq!(ocb.decrypt(&nonce, b"", &ct, &tag), Some(Vec::new()));
+    }
+
+    /// `derive_subkeys` must be a deterministic function of (password, salt,
+    /// iterations): same inputs always reconstruct the same 64-subkey
+    /// schedule, and changing any one input changes the schedule.
+    #[test]
+    fn derive_subkeys_is_deterministic_and_salt_sensitive() {
+        let salt_a = [0x42u8; SALT_LEN];
+        let salt_b = [0x43u8; SALT_LEN];
+        let a1 = derive_subkeys("correct horse battery staple", &salt_a, 100);
+        let a2 = derive_subkeys("correct horse battery staple", &salt_a, 100);
+        assert_eq!(a1, a2, "same password/salt/iterations must reproduce the same subkeys");
+
+        let b = derive_subkeys("correct horse battery staple", &salt_b, 100);
+        assert_ne!(a1, b, "changing the salt must change the subkeys");
+
+        let c = derive_subkeys("a different password", &salt_a, 100);
+        assert_ne!(a1, c, "changing the password must change the subkeys");
+
+        let d = derive_subkeys("correct horse battery staple", &salt_a, 101);
+        assert_ne!(a1, d, "changing the iteration count must change the subkeys");
+    }
+
+    #[test]
+    fn ctr_apply_parallel_matches_single_threaded_and_is_its_own_inverse() {
+        let key = *Key::<Gost2_128>::from_slice(&[0x7eu8; 32]);
+        let params = Gost2Params::gost_r3411_94_test_paramset();
+        let plaintext: Vec<u8> = (0..10_000u32).map(|i| (i % 256) as u8).collect();
+
+        let mut single = plaintext.clone();
+        ctr_apply_parallel(&key, params, &mut single, 1);
+
+        let mut parallel = plaintext.clone();
+        ctr_apply_parallel(&key, params, &mut parallel, 4);
+
+        assert_eq!(single, parallel, "splitting the CTR keystream across threads must not change its output");
+        assert_ne!(single, plaintext, "CTR output must differ from the plaintext");
+
+        let mut roundtrip = single.clone();
+        ctr_apply_parallel(&key, params, &mut roundtrip, 4);
+        assert_eq!(roundtrip, plaintext, "CTR mode must be its own inverse");
+    }
+}
+
 /*
  
 Key 1:My secret password!0123456789abc
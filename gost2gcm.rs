@@ -19,6 +19,18 @@ panic = "abort"
 strip = "symbols"
 
 [dependencies]
+argon2 = "0.5"
+ciborium = "0.2"
+serde = { version = "1", features = ["derive"] }
+
+[target.'cfg(target_os = "linux")'.dependencies]
+libc = "0.2"
+
+[features]
+# Swap the table-driven S-box substitution for a branch-free, data-independent
+# one. Slower, but closes the S-box cache-timing side channel for callers who
+# need constant-time behavior regardless of key/plaintext bits.
+constant-time-sbox = []
 
 */
 
@@ -27,6 +39,10 @@ use std::cmp::min;
 use std::env;
 use std::fs::{File, OpenOptions};
 use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::path::Path;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use serde::{Serialize, Deserialize};
 
 // ---------------------- Platform helpers (password, RNG) ----------------------
 
@@ -209,12 +225,192 @@ mod rng {
         }
     }
 
-    pub fn get_iv_16(iv: &mut [u8; 16]) {
-        if secure_random_bytes(iv).is_ok() {
+    pub fn get_random(buf: &mut [u8]) {
+        if secure_random_bytes(buf).is_ok() {
             return;
         }
         eprintln!("WARNING: secure RNG unavailable; using weak time-based fallback.");
-        fallback_weak_rng(iv);
+        fallback_weak_rng(buf);
+    }
+
+    pub fn get_iv_16(iv: &mut [u8; 16]) {
+        get_random(iv);
+    }
+}
+
+/* ---------------------- Secret zeroizing ---------------------- */
+/* Password bytes, derived Argon2 seeds and GOST2 subkey schedules are
+ * wrapped in `Secret` so the backing memory is overwritten with zeros as
+ * soon as the value goes out of scope - including on an early return via
+ * `?` - instead of being left for the allocator to hand to the next
+ * caller unchanged. Zeroing goes through `ptr::write_volatile` plus a
+ * compiler fence so it can't be optimized away as a dead store to a value
+ * that's about to be dropped. */
+trait VolatileZero {
+    fn volatile_zero(&mut self);
+}
+
+impl<const N: usize> VolatileZero for [u8; N] {
+    fn volatile_zero(&mut self) {
+        for b in self.iter_mut() {
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl<const N: usize> VolatileZero for [u64; N] {
+    fn volatile_zero(&mut self) {
+        for w in self.iter_mut() {
+            unsafe { std::ptr::write_volatile(w, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl VolatileZero for String {
+    fn volatile_zero(&mut self) {
+        // SAFETY: overwriting existing bytes with 0 keeps the buffer valid
+        // UTF-8 (all-NUL is still ASCII) and never touches length/capacity.
+        for b in unsafe { self.as_bytes_mut() } {
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+impl VolatileZero for Vec<u8> {
+    fn volatile_zero(&mut self) {
+        for b in self.iter_mut() {
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+        std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+struct Secret<T: VolatileZero>(T);
+
+impl<T: VolatileZero> Secret<T> {
+    fn new(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: VolatileZero> std::ops::Deref for Secret<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: VolatileZero> std::ops::DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: VolatileZero> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.volatile_zero();
+    }
+}
+
+/* ---------------------- Linux kernel keyring integration ---------------------- */
+/* Optional cache for the derived subkeys in the kernel's per-user keyring,
+ * so a script that encrypts/decrypts the same file repeatedly only has to
+ * prompt for the password once. add_key(2)/keyctl(2) have no libc wrapper
+ * beyond the generic syscall(2) entry point, so this reaches for
+ * `libc::syscall` the same way the rest of this file uses raw platform FFI
+ * (see `rng::secure_random_bytes`) instead of pulling in a keyutils crate. */
+#[cfg(target_os = "linux")]
+mod keyring {
+    use super::Secret;
+    use std::ffi::CString;
+    use std::time::Duration;
+
+    const KEY_TYPE: &str = "user";
+    const KEY_PAYLOAD_LEN: usize = 64 * 8;
+
+    /* A stable, non-secret description derived from the file's salt, so
+     * distinct files (distinct salts) land under distinct keyring entries. */
+    fn key_description(salt: &[u8]) -> CString {
+        let mut desc = String::with_capacity(9 + salt.len() * 2);
+        desc.push_str("gost2gcm:");
+        for b in salt {
+            desc.push_str(&format!("{:02x}", b));
+        }
+        CString::new(desc).expect("hex description has no NUL bytes")
+    }
+
+    fn search_user_keyring(desc: &CString, type_: &CString) -> Option<i64> {
+        let id = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                libc::KEYCTL_SEARCH,
+                libc::KEY_SPEC_USER_KEYRING as i64,
+                type_.as_ptr(),
+                desc.as_ptr(),
+                0i64,
+            )
+        };
+        if id < 0 { None } else { Some(id) }
+    }
+
+    /* Look up an already-cached key for this salt. `None` covers both "no
+     * such key yet" and "found something but it isn't a 512-byte subkey
+     * schedule" - either way the caller falls back to a password prompt. */
+    pub fn search(salt: &[u8]) -> Option<[u64; 64]> {
+        let type_ = CString::new(KEY_TYPE).unwrap();
+        let desc = key_description(salt);
+        let key_id = search_user_keyring(&desc, &type_)?;
+
+        let mut buf = Secret::new([0u8; KEY_PAYLOAD_LEN]);
+        let n = unsafe {
+            libc::syscall(libc::SYS_keyctl, libc::KEYCTL_READ, key_id, buf.as_mut_ptr(), buf.len())
+        };
+        if n != KEY_PAYLOAD_LEN as i64 {
+            return None;
+        }
+        let mut key = [0u64; 64];
+        for (i, word) in key.iter_mut().enumerate() {
+            *word = u64::from_le_bytes(buf[i * 8..i * 8 + 8].try_into().unwrap());
+        }
+        Some(key)
+    }
+
+    /* Cache `key` under the user keyring so a later `search` for the same
+     * salt finds it. Best-effort: failures (no keyring support in this
+     * kernel/container, quota exceeded) are silently ignored since the
+     * caller already has the key it needs regardless. */
+    pub fn add(salt: &[u8], key: &[u64; 64]) {
+        let type_ = CString::new(KEY_TYPE).unwrap();
+        let desc = key_description(salt);
+        let mut payload = Secret::new(Vec::with_capacity(KEY_PAYLOAD_LEN));
+        for word in key {
+            payload.extend_from_slice(&word.to_le_bytes());
+        }
+        unsafe {
+            libc::syscall(
+                libc::SYS_add_key,
+                type_.as_ptr(),
+                desc.as_ptr(),
+                payload.as_ptr(),
+                payload.len(),
+                libc::KEY_SPEC_USER_KEYRING as i64,
+            );
+        }
+    }
+
+    /* Block until a key for this salt shows up in the keyring, polling once
+     * a second - mirroring how mount helpers unblock once another process
+     * provisions the key they're waiting on. */
+    pub fn wait_for(salt: &[u8]) -> [u64; 64] {
+        loop {
+            if let Some(key) = search(salt) {
+                return key;
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        }
     }
 }
 
@@ -310,6 +506,15 @@ impl HashState {
     }
 }
 
+/* The hashing state holds the Argon2-derived seed folded in byte by byte
+ * (h1/h2); wipe it on drop so it doesn't linger past end_hash(). */
+impl Drop for HashState {
+    fn drop(&mut self) {
+        self.h1.volatile_zero();
+        self.h2.volatile_zero();
+    }
+}
+
 fn create_keys(h4: &[u8; N1]) -> [Word64; 64] {
     // create 64 * 64-bit subkeys from h4 hash
     let mut key = [0u64; 64];
@@ -343,16 +548,27 @@ static K14_: [u8; 16]  = [ 0x5,0xD,0xF,0x6,0x9,0x2,0xC,0xA,0xB,0x7,0x8,0x1,0x4,0
 static K15_: [u8; 16]  = [ 0x8,0xE,0x2,0x5,0x6,0x9,0x1,0xC,0xF,0x4,0xB,0x0,0xD,0xA,0x3,0x7 ];
 static K16_: [u8; 16]  = [ 0x1,0x7,0xE,0xD,0x0,0x5,0x8,0x3,0x4,0xF,0xA,0x6,0x9,0xC,0xB,0x2 ];
 
-/* Precomputed tables built from S-boxes (lazy one-time init) */
+/* Precomputed tables built from S-boxes (lazy one-time init). Only the
+ * data-dependent fast path (`f_gost` below, not(constant-time-sbox)) ever
+ * reads these; under `constant-time-sbox` they'd sit unwritten and unread. */
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K175: [u8; 256] = [0; 256];
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K153: [u8; 256] = [0; 256];
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K131: [u8; 256] = [0; 256];
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K109: [u8; 256] = [0; 256];
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K87:  [u8; 256] = [0; 256];
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K65:  [u8; 256] = [0; 256];
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K43:  [u8; 256] = [0; 256];
+#[cfg(not(feature = "constant-time-sbox"))]
 static mut K21:  [u8; 256] = [0; 256];
 
+#[cfg(not(feature = "constant-time-sbox"))]
 fn kboxinit() {
     // safe wrapper around once-only init
     static ONCE: std::sync::Once = std::sync::Once::new();
@@ -370,9 +586,60 @@ fn kboxinit() {
     });
 }
 
+#[cfg(feature = "constant-time-sbox")]
+fn kboxinit() {
+    // nothing to precompute: the constant-time path reads the 16-entry
+    // nibble tables directly via a branch-free scan, never the byte tables
+}
+
+/* Branch-free lookup into a 16-entry nibble S-box: for each candidate `i`,
+ * `m` is 0xFF when `n == i` and 0x00 otherwise, so the OR-accumulation
+ * selects exactly one entry without ever indexing memory by `n`. */
+#[cfg(feature = "constant-time-sbox")]
+#[inline]
+fn sbox_lookup_ct(table: &[u8; 16], n: u8) -> u8 {
+    let mut out = 0u8;
+    for i in 0..16u8 {
+        let m = (((n ^ i) as u16).wrapping_sub(1) >> 8) as u8;
+        out |= m & table[i as usize];
+    }
+    out
+}
+
+#[cfg(feature = "constant-time-sbox")]
+#[inline]
+fn subst_byte_ct(hi_tab: &[u8; 16], lo_tab: &[u8; 16], b: u8) -> u8 {
+    (sbox_lookup_ct(hi_tab, b >> 4) << 4) | sbox_lookup_ct(lo_tab, b & 0xF)
+}
+
+#[cfg(feature = "constant-time-sbox")]
 #[inline]
 fn f_gost(x: u64) -> u64 {
-    // use precomputed tables to apply S-boxes nibble-wise then rotate left by 11
+    // constant-time path: every nibble substitution is a branch-free scan
+    // over all 16 S-box entries instead of a data-dependent table index
+    let y = (x >> 32) as u32;
+    let z = (x & 0xffff_ffff) as u32;
+
+    let y = ((subst_byte_ct(&K8_, &K7_, ((y >> 24) & 0xFF) as u8) as u64) << 24)
+      | ((subst_byte_ct(&K6_, &K5_, ((y >> 16) & 0xFF) as u8) as u64) << 16)
+      | ((subst_byte_ct(&K4_, &K3_, ((y >>  8) & 0xFF) as u8) as u64) <<  8)
+      |  (subst_byte_ct(&K2_, &K1_, (y & 0xFF) as u8) as u64);
+    let z = ((subst_byte_ct(&K16_, &K15_, ((z >> 24) & 0xFF) as u8) as u64) << 24)
+      | ((subst_byte_ct(&K14_, &K13_, ((z >> 16) & 0xFF) as u8) as u64) << 16)
+      | ((subst_byte_ct(&K12_, &K11_, ((z >>  8) & 0xFF) as u8) as u64) <<  8)
+      |  (subst_byte_ct(&K10_, &K9_, (z & 0xFF) as u8) as u64);
+
+    let x = (y << 32) | (z & 0xffff_ffff);
+    x.rotate_left(11)
+}
+
+#[cfg(not(feature = "constant-time-sbox"))]
+#[inline]
+fn f_gost(x: u64) -> u64 {
+    // fast path: precomputed byte tables applied via data-dependent indexing.
+    // Leaks access-pattern timing through the data cache; only use where
+    // the S-box cache-timing side channel is out of scope (enable the
+    // `constant-time-sbox` feature otherwise).
     let y = (x >> 32) as u32;
     let z = (x & 0xffff_ffff) as u32;
     unsafe {
@@ -448,26 +715,179 @@ fn be128_shl1(v: Be128) -> Be128 {
     }
 }
 
-/* GF(2^128) multiplication per SP 800-38D, right-shift method */
+/* plain (unreduced) right shift by 4 bits */
+fn be128_shr4_plain(v: Be128) -> Be128 {
+    Be128 { lo: (v.lo >> 4) | ((v.hi & 0xF) << 60), hi: v.hi >> 4 }
+}
+
+// R = 0xE1000000000000000000000000000000 (big-endian)
+const GF_R: Be128 = Be128 { hi: 0xE100000000000000u64, lo: 0x0000000000000000u64 };
+
+/* single right-shift-with-reduce step: the building block both the bit-serial
+ * multiplier and the windowed-table construction below share */
+fn shr1_reduce(v: Be128) -> Be128 {
+    let lsb = (v.lo & 1u64) != 0;
+    let v = be128_shr1(v);
+    if lsb { be128_xor(v, GF_R) } else { v }
+}
+
+/* GF(2^128) multiplication per SP 800-38D, right-shift method (bit-serial
+ * reference implementation; not on the GHASH path, kept as the reference the
+ * tests check the windowed-table and CLMUL paths against) */
+#[allow(dead_code)]
 fn gf_mult(mut x: Be128, mut y: Be128) -> Be128 {
     let mut z = Be128 { hi: 0, lo: 0 };
-    // R = 0xE1000000000000000000000000000000 (big-endian)
-    const R: Be128 = Be128 { hi: 0xE100000000000000u64, lo: 0x0000000000000000u64 };
     for _ in 0..128 {
         let msb = (x.hi & 0x8000_0000_0000_0000u64) != 0;
         if msb { z = be128_xor(z, y); }
-        let lsb = (y.lo & 1u64) != 0;
-        y = be128_shr1(y);
-        if lsb { y = be128_xor(y, R); }
+        y = shr1_reduce(y);
         x = be128_shl1(x);
     }
     z
 }
 
+#[cfg(target_arch = "x86_64")]
+mod clmul {
+    use super::Be128;
+    use core::arch::x86_64::*;
+
+    /* Carry-less multiply + GCM reduction (the classic 4-clmul/Karatsuba
+     * sequence), operating on the same big-endian block layout as
+     * `load_be128`/`store_be128`. Caller must have checked
+     * `is_x86_feature_detected!("pclmulqdq")` and `("sse2")`. */
+    #[target_feature(enable = "pclmulqdq,sse2")]
+    pub unsafe fn gfmul(x: Be128, h: Be128) -> Be128 {
+        let mut xb = [0u8; 16];
+        let mut hb = [0u8; 16];
+        super::store_be128(x, &mut xb);
+        super::store_be128(h, &mut hb);
+        // pclmulqdq treats a __m128i as a natural-order polynomial (byte 0 is
+        // the low-order byte), while `Be128`/`load_be128`/`store_be128` use
+        // GCM's big-endian byte order. Reverse the whole 16-byte block going
+        // in, and again on the way out, to cross between the two domains.
+        xb.reverse();
+        hb.reverse();
+        let a = _mm_loadu_si128(xb.as_ptr() as *const __m128i);
+        let b = _mm_loadu_si128(hb.as_ptr() as *const __m128i);
+
+        let tmp3 = _mm_clmulepi64_si128(a, b, 0x00);
+        let tmp4 = _mm_clmulepi64_si128(a, b, 0x10);
+        let tmp5 = _mm_clmulepi64_si128(a, b, 0x01);
+        let tmp6 = _mm_clmulepi64_si128(a, b, 0x11);
+
+        let tmp4 = _mm_xor_si128(tmp4, tmp5);
+        let tmp5 = _mm_slli_si128(tmp4, 8);
+        let tmp4 = _mm_srli_si128(tmp4, 8);
+        let tmp3 = _mm_xor_si128(tmp3, tmp5);
+        let tmp6 = _mm_xor_si128(tmp6, tmp4);
+
+        let tmp7 = _mm_srli_epi32(tmp3, 31);
+        let tmp8 = _mm_srli_epi32(tmp6, 31);
+        let tmp3 = _mm_slli_epi32(tmp3, 1);
+        let tmp6 = _mm_slli_epi32(tmp6, 1);
+
+        let tmp9 = _mm_srli_si128(tmp7, 12);
+        let tmp8b = _mm_slli_si128(tmp8, 4);
+        let tmp7 = _mm_slli_si128(tmp7, 4);
+        let tmp3 = _mm_or_si128(tmp3, tmp7);
+        let tmp6 = _mm_or_si128(tmp6, tmp8b);
+        let tmp6 = _mm_or_si128(tmp6, tmp9);
+
+        let tmp7 = _mm_slli_epi32(tmp3, 31);
+        let tmp8 = _mm_slli_epi32(tmp3, 30);
+        let tmp9 = _mm_slli_epi32(tmp3, 25);
+
+        let tmp7 = _mm_xor_si128(tmp7, tmp8);
+        let tmp7 = _mm_xor_si128(tmp7, tmp9);
+        let tmp8 = _mm_srli_si128(tmp7, 4);
+        let tmp7 = _mm_slli_si128(tmp7, 12);
+        let tmp3 = _mm_xor_si128(tmp3, tmp7);
+
+        let tmp2 = _mm_srli_epi32(tmp3, 1);
+        let tmp4 = _mm_srli_epi32(tmp3, 2);
+        let tmp5 = _mm_srli_epi32(tmp3, 7);
+        let tmp2 = _mm_xor_si128(tmp2, tmp4);
+        let tmp2 = _mm_xor_si128(tmp2, tmp5);
+        let tmp2 = _mm_xor_si128(tmp2, tmp8);
+        let tmp3 = _mm_xor_si128(tmp3, tmp2);
+        let tmp6 = _mm_xor_si128(tmp6, tmp3);
+
+        let mut out = [0u8; 16];
+        _mm_storeu_si128(out.as_mut_ptr() as *mut __m128i, tmp6);
+        out.reverse();
+        super::load_be128(&out)
+    }
+}
+
+/* Shoup-style 4-bit windowed GHASH, built once per key (`H` never changes
+ * across a file), plus a runtime-detected CLMUL fast path. Both paths and
+ * the bit-serial reference above produce identical tags. */
+struct GhashTables {
+    h: Be128,
+    m: [Be128; 16],
+    rtab: [Be128; 16],
+    #[cfg(target_arch = "x86_64")]
+    clmul: bool,
+}
+
+impl GhashTables {
+    fn new(h: Be128) -> Self {
+        // M[1] = H; M[2k] = double(M[k]); M[2k+1] = M[2k] xor H
+        let mut m = [Be128 { hi: 0, lo: 0 }; 16];
+        m[1] = h;
+        for k in 1..8usize {
+            m[2 * k] = shr1_reduce(m[k]);
+            m[2 * k + 1] = be128_xor(m[2 * k], h);
+        }
+        // Rtab[v] = result of reducing a shift-right-by-4 of a register
+        // whose only set bits are the 4-bit value v
+        let mut rtab = [Be128 { hi: 0, lo: 0 }; 16];
+        for v in 0u8..16 {
+            let mut x = Be128 { hi: 0, lo: v as u64 };
+            for _ in 0..4 { x = shr1_reduce(x); }
+            rtab[v as usize] = x;
+        }
+        GhashTables {
+            h,
+            m,
+            rtab,
+            #[cfg(target_arch = "x86_64")]
+            clmul: is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse2"),
+        }
+    }
+
+    fn mult(&self, x: Be128) -> Be128 {
+        #[cfg(target_arch = "x86_64")]
+        if self.clmul {
+            return unsafe { clmul::gfmul(x, self.h) };
+        }
+        // Z = (Z reduced-shift-right by 4) xor M[nibble], LSB nibble first;
+        // each nibble is bit-reversed before the table lookup because bit j
+        // of an M[] index selects H * x^j (M[2k] = shr1_reduce(M[k])), while
+        // within a nibble of x the MSB is the lowest-degree coefficient, so
+        // the bit that selects M[1] vs M[0] must be the nibble's MSB, not its LSB.
+        let mut z = Be128 { hi: 0, lo: 0 };
+        for nibble_idx in 0..32 {
+            let shift = nibble_idx * 4;
+            let nib = if shift < 64 {
+                ((x.lo >> shift) & 0xF) as u8
+            } else {
+                ((x.hi >> (shift - 64)) & 0xF) as u8
+            };
+            let nib = nib.reverse_bits() >> 4;
+            let low = (z.lo & 0xF) as u8;
+            z = be128_shr4_plain(z);
+            z = be128_xor(z, self.rtab[low as usize]);
+            z = be128_xor(z, self.m[nib as usize]);
+        }
+        z
+    }
+}
+
 /* GHASH update: Y <- (Y ^ X) * H */
-fn ghash_update(y: &mut Be128, h: Be128, block16: &[u8; 16]) {
+fn ghash_update(y: &mut Be128, tables: &GhashTables, block16: &[u8; 16]) {
     let x = load_be128(block16);
-    *y = gf_mult(be128_xor(*y, x), h);
+    *y = tables.mult(be128_xor(*y, x));
 }
 
 /* Encrypt a single 16-byte block with GOST2-128 */
@@ -494,7 +914,7 @@ fn inc32(ctr: &mut [u8; 16]) {
 }
 
 /* Derive J0 from IV (generic case when IV != 12 bytes) */
-fn derive_j0(j0: &mut [u8; 16], iv: &[u8], hbe: Be128) {
+fn derive_j0(j0: &mut [u8; 16], iv: &[u8], tables: &GhashTables) {
     // Y = 0
     let mut y = Be128 { hi: 0, lo: 0 };
     let mut block = [0u8; 16];
@@ -504,7 +924,7 @@ fn derive_j0(j0: &mut [u8; 16], iv: &[u8], hbe: Be128) {
     while iv.len().saturating_sub(off) >= 16 {
         let mut b = [0u8; 16];
         b.copy_from_slice(&iv[off..off+16]);
-        ghash_update(&mut y, hbe, &b);
+        ghash_update(&mut y, tables, &b);
         off += 16;
     }
     // Last partial block (pad with zeros)
@@ -512,24 +932,24 @@ fn derive_j0(j0: &mut [u8; 16], iv: &[u8], hbe: Be128) {
         let rem = iv.len() - off;
         block.fill(0);
         block[..rem].copy_from_slice(&iv[off..]);
-        ghash_update(&mut y, hbe, &block);
+        ghash_update(&mut y, tables, &block);
     }
     // Append 128-bit length block: 64-bit zeros || [len(IV) in bits]_64
     block.fill(0);
     let ivbits = (iv.len() as u64).wrapping_mul(8);
     block[8..].copy_from_slice(&ivbits.to_be_bytes());
-    ghash_update(&mut y, hbe, &block);
+    ghash_update(&mut y, tables, &block);
 
     store_be128(y, j0);
 }
 
 /* Prepares GHASH lengths block for AAD(empty) and C(lenC) */
-fn ghash_lengths_update(y: &mut Be128, hbe: Be128, _aad_bits: u64, c_bits: u64) {
+fn ghash_lengths_update(y: &mut Be128, tables: &GhashTables, aad_bits: u64, c_bits: u64) {
     let mut lenblk = [0u8; 16];
     // [len(AAD)]_64 || [len(C)]_64 in bits, both big-endian
-    // AAD is zero here
+    lenblk[..8].copy_from_slice(&aad_bits.to_be_bytes());
     lenblk[8..].copy_from_slice(&c_bits.to_be_bytes());
-    ghash_update(y, hbe, &lenblk);
+    ghash_update(y, tables, &lenblk);
 }
 
 /* Constant-time tag comparison */
@@ -542,6 +962,137 @@ fn ct_memcmp(a: &[u8], b: &[u8]) -> u8 {
     r | ((a.len() ^ b.len()) as u8)
 }
 
+// ---------------------- GOST2-CMAC (OMAC1) ----------------------
+/*
+ * A standalone keyed MAC built from the same GOST2-128 block cipher, per
+ * NIST SP 800-38B. This is independent of the GCM file format above: it
+ * gives integrity protection for callers who don't want (or can't afford)
+ * full encryption, via a detached 16-byte tag stored next to the file.
+ *
+ * Note this is GF(2^128) doubling in CMAC's own convention: left shift with
+ * conditional XOR of Rb = 0x87 into the low byte on carry-out of the top
+ * bit. That's the opposite shift direction from the GCM GHASH doubling
+ * above (right-shift, XOR with R in the top byte) -- the two moduli are
+ * bit-reflections of each other and must not be mixed.
+ */
+fn cmac_double(v: Be128) -> Be128 {
+    let carry = (v.hi & 0x8000_0000_0000_0000) != 0;
+    let mut r = be128_shl1(v);
+    if carry {
+        r.lo ^= 0x87;
+    }
+    r
+}
+
+/* K1/K2 subkey derivation: K1 = double(E_K(0)); K2 = double(K1) */
+fn cmac_subkeys(key: &[u64; 64]) -> (Be128, Be128) {
+    let zero = [0u8; 16];
+    let mut l = [0u8; 16];
+    gost_encrypt_block(&zero, &mut l, key);
+    let k1 = cmac_double(load_be128(&l));
+    let k2 = cmac_double(k1);
+    (k1, k2)
+}
+
+/* CBC-MAC over 16-byte blocks, read_stream_segment-style so the file is
+ * streamed rather than loaded whole; the final block is XORed with K1 (full
+ * block) or 10*-padded and XORed with K2 (partial/empty final block) before
+ * the last encryption. */
+fn gost_cmac_file<R: Read>(r: &mut R, key: &[u64; 64]) -> io::Result<[u8; 16]> {
+    let (k1, k2) = cmac_subkeys(key);
+    let mut mac = [0u8; 16];
+    let mut carry: Option<u8> = None;
+    let mut buf = [0u8; 16];
+    loop {
+        let (n, is_last) = read_stream_segment(r, &mut carry, &mut buf)?;
+        let mut block = [0u8; 16];
+        if is_last {
+            if n == 16 {
+                let blk_be = be128_xor(load_be128(&buf), k1);
+                store_be128(blk_be, &mut block);
+            } else {
+                block[..n].copy_from_slice(&buf[..n]);
+                block[n] = 0x80;
+                let blk_be = be128_xor(load_be128(&block), k2);
+                store_be128(blk_be, &mut block);
+            }
+        } else {
+            block.copy_from_slice(&buf);
+        }
+        for j in 0..16 { block[j] ^= mac[j]; }
+        let mut out = [0u8; 16];
+        gost_encrypt_block(&block, &mut out, key);
+        mac = out;
+        if is_last { break; }
+    }
+    Ok(mac)
+}
+
+/* Detached-tag file format: salt(16) || kdf_params(12) || tag(16), written
+ * next to the input file as `<input>.gmac`. */
+fn gmac_sidecar_name(input: &str) -> String {
+    format!("{input}.gmac")
+}
+
+/* Read just the salt out of a `.gmac` sidecar, without touching the file
+ * being verified, so callers can try a keyring lookup before prompting. */
+fn peek_mac_salt(infile: &str) -> io::Result<[u8; SALT_LEN]> {
+    let mut sf = File::open(gmac_sidecar_name(infile))?;
+    let mut salt = [0u8; SALT_LEN];
+    sf.read_exact(&mut salt)?;
+    Ok(salt)
+}
+
+fn mac_file(infile: &str, cred: Credential, params: &KdfParams, use_keyring: bool) -> io::Result<()> {
+    let mut fi = File::open(infile)?;
+    let mut salt = [0u8; SALT_LEN];
+    rng::get_random(&mut salt);
+    let from_password = cred.is_password();
+    let key = cred.resolve(&salt, params);
+    if from_password {
+        keyring_cache(&salt, &key, use_keyring);
+    }
+    let tag = gost_cmac_file(&mut fi, &key)?;
+
+    let sidecar = gmac_sidecar_name(infile);
+    let fo = OpenOptions::new().write(true).create(true).truncate(true).open(&sidecar)?;
+    let mut bw = BufWriter::new(fo);
+    bw.write_all(&salt)?;
+    write_kdf_params(&mut bw, params)?;
+    bw.write_all(&tag)?;
+    bw.flush()?;
+
+    println!("Wrote detached MAC to {}", sidecar);
+    Ok(())
+}
+
+fn verify_mac_file(infile: &str, cred: Credential, use_keyring: bool) -> io::Result<i32> {
+    let sidecar = gmac_sidecar_name(infile);
+    let mut sf = File::open(&sidecar)?;
+
+    let mut salt = [0u8; SALT_LEN];
+    sf.read_exact(&mut salt)?;
+    let params = read_kdf_params(&mut sf, "MAC header")?;
+    let mut stored_tag = [0u8; 16];
+    sf.read_exact(&mut stored_tag)?;
+
+    let from_password = cred.is_password();
+    let key = cred.resolve(&salt, &params);
+    if from_password {
+        keyring_cache(&salt, &key, use_keyring);
+    }
+    let mut fi = File::open(infile)?;
+    let tag = gost_cmac_file(&mut fi, &key)?;
+
+    if ct_memcmp(&stored_tag, &tag) == 0 {
+        println!("MAC verify: OK");
+        Ok(0)
+    } else {
+        println!("MAC verify: FAILED");
+        Ok(1)
+    }
+}
+
 // ---------------------- File name helpers ----------------------
 fn add_suffix_gost2(input: &str) -> String {
     format!("{input}.gost2")
@@ -559,27 +1110,278 @@ fn strip_suffix_gost2(input: &str) -> String {
 
 const BUF_CHUNK: usize = 4096;
 
-fn encrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<()> {
+/* GHASH the associated data as zero-padded 16-byte blocks, ahead of the
+ * ciphertext blocks, so it is bound to the tag without being encrypted. */
+fn ghash_aad(s: &mut Be128, tables: &GhashTables, aad: &[u8]) {
+    let mut off = 0usize;
+    while aad.len().saturating_sub(off) >= 16 {
+        let mut b = [0u8; 16];
+        b.copy_from_slice(&aad[off..off + 16]);
+        ghash_update(s, tables, &b);
+        off += 16;
+    }
+    if aad.len() > off {
+        let mut b = [0u8; 16];
+        b[..aad.len() - off].copy_from_slice(&aad[off..]);
+        ghash_update(s, tables, &b);
+    }
+}
+
+/* ---------------------- STREAM chunked AEAD framing ---------------------- */
+/*
+ * The single-tag format above only checks the GCM tag after every ciphertext
+ * byte has already been written to disk: a truncated or tampered file leaves
+ * unverified plaintext sitting on disk before decrypt_file ever notices
+ * ("release of unverified plaintext"). The STREAM format instead splits the
+ * file into fixed-size segments, each sealed with its own GCM tag derived
+ * from a per-segment 12-byte nonce (7-byte random prefix || segment_counter
+ * || last_flag), and verifies a segment's tag before writing any of that
+ * segment's plaintext. The flag byte is 0x01 only on the true final
+ * segment; since it's folded into the authenticated nonce, truncating the
+ * file right after a full (non-final) segment changes which segment decrypt
+ * computes as "last" and the tag no longer matches, so the attack is caught
+ * rather than silently accepted.
+ */
+
+const STREAM_SEGMENT_SIZE: usize = 256 * 1024;
+const FORMAT_SINGLE: u8 = 0;
+const FORMAT_STREAM: u8 = 1;
+
+fn stream_segment_nonce(base_iv: &[u8; 7], counter: u32, last: bool) -> [u8; 12] {
+    let mut n = [0u8; 12];
+    n[..7].copy_from_slice(base_iv);
+    n[7..11].copy_from_slice(&counter.to_be_bytes());
+    n[11] = last as u8;
+    n
+}
+
+/* Seal one segment: full GCM encrypt + tag over `plaintext`, using `nonce`
+ * (of whatever length; derive_j0 folds any IV length into J0 via GHASH) as
+ * the per-segment IV. */
+fn gcm_seal_segment(key: &[u64; 64], tables: &GhashTables, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> (Vec<u8>, [u8; 16]) {
+    let mut j0 = [0u8; 16];
+    derive_j0(&mut j0, nonce, tables);
+    let mut ctr = j0;
+    inc32(&mut ctr);
+
+    let mut s = Be128 { hi: 0, lo: 0 };
+    ghash_aad(&mut s, tables, aad);
+
+    let mut ciphertext = Vec::with_capacity(plaintext.len());
+    let mut off = 0usize;
+    while off < plaintext.len() {
+        let n = min(16, plaintext.len() - off);
+        let mut ks = [0u8; 16];
+        gost_encrypt_block(&ctr, &mut ks, key);
+        inc32(&mut ctr);
+        let mut cblk = [0u8; 16];
+        for i in 0..n { cblk[i] = plaintext[off + i] ^ ks[i]; }
+        ghash_update(&mut s, tables, &cblk);
+        ciphertext.extend_from_slice(&cblk[..n]);
+        off += n;
+    }
+    ghash_lengths_update(&mut s, tables, aad.len().wrapping_mul(8) as u64, plaintext.len().wrapping_mul(8) as u64);
+
+    let mut ej0 = [0u8; 16];
+    gost_encrypt_block(&j0, &mut ej0, key);
+    let mut sbytes = [0u8; 16];
+    store_be128(s, &mut sbytes);
+    let mut tag = [0u8; 16];
+    for i in 0..16 { tag[i] = ej0[i] ^ sbytes[i]; }
+    (ciphertext, tag)
+}
+
+/* Open one segment: verify the tag first, and only return plaintext on a
+ * match, so the caller can write plaintext for a segment iff it authenticates. */
+fn gcm_open_segment(key: &[u64; 64], tables: &GhashTables, nonce: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8; 16]) -> Option<Vec<u8>> {
+    let mut j0 = [0u8; 16];
+    derive_j0(&mut j0, nonce, tables);
+    let mut ctr = j0;
+    inc32(&mut ctr);
+
+    let mut s = Be128 { hi: 0, lo: 0 };
+    ghash_aad(&mut s, tables, aad);
+
+    let mut plaintext = Vec::with_capacity(ciphertext.len());
+    let mut off = 0usize;
+    while off < ciphertext.len() {
+        let n = min(16, ciphertext.len() - off);
+        let mut cblk = [0u8; 16];
+        cblk[..n].copy_from_slice(&ciphertext[off..off + n]);
+        ghash_update(&mut s, tables, &cblk);
+        let mut ks = [0u8; 16];
+        gost_encrypt_block(&ctr, &mut ks, key);
+        inc32(&mut ctr);
+        let mut pblk = [0u8; 16];
+        for i in 0..n { pblk[i] = cblk[i] ^ ks[i]; }
+        plaintext.extend_from_slice(&pblk[..n]);
+        off += n;
+    }
+    ghash_lengths_update(&mut s, tables, aad.len().wrapping_mul(8) as u64, ciphertext.len().wrapping_mul(8) as u64);
+
+    let mut ej0 = [0u8; 16];
+    gost_encrypt_block(&j0, &mut ej0, key);
+    let mut sbytes = [0u8; 16];
+    store_be128(s, &mut sbytes);
+    let mut tcalc = [0u8; 16];
+    for i in 0..16 { tcalc[i] = ej0[i] ^ sbytes[i]; }
+
+    if ct_memcmp(tag, &tcalc) == 0 { Some(plaintext) } else { None }
+}
+
+/* Reads up to `buf.len()` bytes (a full segment), carrying over any byte
+ * peeked by the previous call. Returns (bytes_filled, is_last_segment);
+ * "last" is only known once we've peeked one byte past a full segment and
+ * found EOF, so the true final segment is never mistaken for a full one. */
+fn read_stream_segment<R: Read>(br: &mut R, carry: &mut Option<u8>, buf: &mut [u8]) -> io::Result<(usize, bool)> {
+    let mut len = 0usize;
+    if let Some(b) = carry.take() {
+        buf[0] = b;
+        len = 1;
+    }
+    while len < buf.len() {
+        let r = br.read(&mut buf[len..])?;
+        if r == 0 { break; }
+        len += r;
+    }
+    if len < buf.len() {
+        return Ok((len, true));
+    }
+    let mut one = [0u8; 1];
+    if br.read(&mut one)? == 0 {
+        Ok((len, true))
+    } else {
+        *carry = Some(one[0]);
+        Ok((len, false))
+    }
+}
+
+fn encrypt_file_stream<R: Read>(br: &mut R, bw: &mut impl Write, key: &[u64; 64], ghash_tables: &GhashTables, aad: &[u8], base_iv: &[u8; 7]) -> io::Result<()> {
+    let mut buf = vec![0u8; STREAM_SEGMENT_SIZE];
+    let mut carry: Option<u8> = None;
+    let mut counter: u32 = 0;
+    loop {
+        let (n, is_last) = read_stream_segment(br, &mut carry, &mut buf)?;
+        let nonce = stream_segment_nonce(base_iv, counter, is_last);
+        let (ciphertext, tag) = gcm_seal_segment(key, ghash_tables, &nonce, aad, &buf[..n]);
+        bw.write_all(&ciphertext)?;
+        bw.write_all(&tag)?;
+        counter = counter.checked_add(1).expect("stream segment counter overflow");
+        if is_last { break; }
+    }
+    Ok(())
+}
+
+fn decrypt_file_stream(fi: &mut File, bw: &mut impl Write, key: &[u64; 64], ghash_tables: &GhashTables, aad: &[u8], base_iv: &[u8; 7], mut remaining: i64) -> io::Result<i32> {
+    let mut counter: u32 = 0;
+    loop {
+        if remaining == 0 {
+            if counter == 0 {
+                eprintln!("Malformed stream body: no segments.");
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "no segments"));
+            }
+            break;
+        }
+        if remaining < 16 {
+            eprintln!("Malformed stream segment: missing tag.");
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated segment"));
+        }
+        let chunk_on_disk = min(remaining, (STREAM_SEGMENT_SIZE + 16) as i64);
+        let ciph_len = (chunk_on_disk - 16) as usize;
+        let is_last = remaining == chunk_on_disk;
+
+        let mut ciphertext = vec![0u8; ciph_len];
+        fi.read_exact(&mut ciphertext)?;
+        let mut tag = [0u8; 16];
+        fi.read_exact(&mut tag)?;
+
+        let nonce = stream_segment_nonce(base_iv, counter, is_last);
+        match gcm_open_segment(key, ghash_tables, &nonce, aad, &ciphertext, &tag) {
+            Some(plaintext) => {
+                bw.write_all(&plaintext)?;
+            }
+            None => {
+                bw.flush()?;
+                println!("Authentication: FAILED (segment {})", counter);
+                return Ok(1);
+            }
+        }
+
+        remaining -= chunk_on_disk;
+        counter += 1;
+        if is_last { break; }
+    }
+    bw.flush()?;
+    println!("Authentication: OK");
+    Ok(0)
+}
+
+fn encrypt_file(infile: &str, outfile: &str, cred: Credential, kdf_params: &KdfParams, aad: &[u8], stream: bool, use_keyring: bool) -> io::Result<()> {
     let fi = File::open(infile)?;
     let mut br = BufReader::new(fi);
     let fo = OpenOptions::new().write(true).create(true).truncate(true).open(outfile)?;
     let mut bw = BufWriter::new(fo);
 
+    /* Per-file random salt and nonce/counter prefix, bundled with the Argon2id
+     * cost parameters, format and cipher identifiers, and chunk size into a
+     * single self-describing CBOR header. This is what lets a future version
+     * of the tool add a new KDF or chunk size without breaking the ability to
+     * at least recognize and reject files it doesn't understand. */
+    let mut salt = [0u8; SALT_LEN];
+    rng::get_random(&mut salt);
+    let format_byte = if stream { FORMAT_STREAM } else { FORMAT_SINGLE };
+
+    let nonce_prefix = if stream {
+        let mut base_iv = [0u8; 7];
+        rng::get_random(&mut base_iv);
+        base_iv.to_vec()
+    } else {
+        let mut iv = [0u8; 16];
+        rng::get_iv_16(&mut iv);
+        iv.to_vec()
+    };
+
+    let header = FileHeader {
+        kdf_id: KDF_ID_ARGON2ID,
+        kdf: *kdf_params,
+        salt: salt.to_vec(),
+        format: format_byte,
+        nonce_prefix: nonce_prefix.clone(),
+        chunk_size: if stream { STREAM_SEGMENT_SIZE as u32 } else { 0 },
+        cipher: CIPHER_GOST2_128_GCM,
+    };
+    let header_bytes = write_file_header(&mut bw, &header)?;
+
+    let from_password = cred.is_password();
+    let key = cred.resolve(&salt, kdf_params);
+    if from_password {
+        keyring_cache(&salt, &key, use_keyring);
+    }
+
     /* Compute H and J0 */
-    let mut h = [0u8; 16]; compute_h(&mut h, key);
+    let mut h = [0u8; 16]; compute_h(&mut h, &key);
     let hbe = load_be128(&h);
+    let ghash_tables = GhashTables::new(hbe);
 
-    let mut iv = [0u8; 16];
-    rng::get_iv_16(&mut iv);
+    let filename = header_aad_filename(infile);
+    let combined_aad = bind_header_aad(&header_bytes, &filename, aad);
+
+    if stream {
+        let base_iv: [u8; 7] = nonce_prefix.try_into().expect("stream nonce_prefix is 7 bytes");
+        encrypt_file_stream(&mut br, &mut bw, &key, &ghash_tables, &combined_aad, &base_iv)?;
+        bw.flush()?;
+        println!("Encryption completed. Wrote salted STREAM-framed ciphertext.");
+        return Ok(());
+    }
 
-    /* Write IV (16 bytes) */
-    bw.write_all(&iv)?;
+    let iv: [u8; 16] = nonce_prefix.try_into().expect("single-shot nonce_prefix is 16 bytes");
 
     let mut j0 = [0u8; 16];
-    derive_j0(&mut j0, &iv, hbe);
+    derive_j0(&mut j0, &iv, &ghash_tables);
 
-    /* S = GHASH over ciphertext (starts at 0) */
+    /* S = GHASH over AAD then ciphertext (starts at 0) */
     let mut s = Be128 { hi: 0, lo: 0 };
+    ghash_aad(&mut s, &ghash_tables, &combined_aad);
 
     /* Counter starts from inc32(J0) */
     let mut ctr = j0;
@@ -597,7 +1399,7 @@ fn encrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<()>
             let n = min(16, r - off);
             // keystream = E_K(ctr)
             let mut ks = [0u8; 16];
-            gost_encrypt_block(&ctr, &mut ks, key);
+            gost_encrypt_block(&ctr, &mut ks, &key);
             inc32(&mut ctr);
 
             // P block (pad with zeros for XOR; we only write n bytes)
@@ -609,7 +1411,7 @@ fn encrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<()>
             if n < 16 { for i in n..16 { cblk[i] = 0; } } // pad for GHASH
 
             // Update GHASH with ciphertext block (padded for partial)
-            ghash_update(&mut s, hbe, &cblk);
+            ghash_update(&mut s, &ghash_tables, &cblk);
 
             // Write ciphertext bytes (only n bytes)
             bw.write_all(&cblk[..n])?;
@@ -619,12 +1421,12 @@ fn encrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<()>
         }
     }
 
-    /* S <- S ⊗ H with lengths block (AAD=0, C=total_c_bytes) */
-    ghash_lengths_update(&mut s, hbe, 0, total_c_bytes.wrapping_mul(8));
+    /* S <- S ⊗ H with lengths block (AAD=combined_aad, C=total_c_bytes) */
+    ghash_lengths_update(&mut s, &ghash_tables, combined_aad.len().wrapping_mul(8) as u64, total_c_bytes.wrapping_mul(8));
 
     /* Tag T = E_K(J0) XOR S */
     let mut ej0 = [0u8; 16];
-    gost_encrypt_block(&j0, &mut ej0, key);
+    gost_encrypt_block(&j0, &mut ej0, &key);
     let mut sbytes = [0u8; 16];
     store_be128(s, &mut sbytes);
     let mut tag = [0u8; 16];
@@ -638,19 +1440,62 @@ fn encrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<()>
     Ok(())
 }
 
-fn decrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<i32> {
+fn decrypt_file(infile: &str, outfile: &str, cred: Credential, aad: &[u8], use_keyring: bool) -> io::Result<i32> {
     let mut fi = File::open(infile)?;
     let fsz = fi.metadata()?.len() as i64;
 
-    if fsz < 32 {
-        eprintln!("File too small (needs at least IV+TAG).");
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "file too small"));
+    let (header, header_bytes) = read_file_header(&mut fi)?;
+    let consumed = header_bytes.len() as i64;
+
+    if header.salt.len() != SALT_LEN {
+        eprintln!("Malformed header: salt has wrong length.");
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad salt length"));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&header.salt);
+
+    let from_password = cred.is_password();
+    let key = cred.resolve(&salt, &header.kdf);
+    if from_password {
+        keyring_cache(&salt, &key, use_keyring);
+    }
+
+    /* Compute H as in encryption; J0 itself depends on the per-format IV/nonce */
+    let mut h = [0u8; 16]; compute_h(&mut h, &key);
+    let hbe = load_be128(&h);
+    let ghash_tables = GhashTables::new(hbe);
+
+    // Bind the *plaintext's* basename, matching what encrypt_file bound: the
+    // ciphertext file is named `<original>.gost2`, and outfile (derived via
+    // strip_suffix_gost2 in main) reconstructs that original name.
+    let filename = header_aad_filename(outfile);
+    let combined_aad = bind_header_aad(&header_bytes, &filename, aad);
+
+    if header.format == FORMAT_STREAM {
+        if header.nonce_prefix.len() != 7 {
+            eprintln!("Malformed header: stream nonce prefix has wrong length.");
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad nonce prefix length"));
+        }
+        let mut base_iv = [0u8; 7];
+        base_iv.copy_from_slice(&header.nonce_prefix);
+        let remaining = fsz - consumed;
+
+        let fo = OpenOptions::new().write(true).create(true).truncate(true).open(outfile)?;
+        let mut bw = BufWriter::new(fo);
+        return decrypt_file_stream(&mut fi, &mut bw, &key, &ghash_tables, &combined_aad, &base_iv, remaining);
+    } else if header.format != FORMAT_SINGLE {
+        eprintln!("Malformed header: unknown format marker {}.", header.format);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unknown format"));
     }
 
-    /* Read IV */
+    if header.nonce_prefix.len() != 16 {
+        eprintln!("Malformed header: single-shot nonce/IV has wrong length.");
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad iv length"));
+    }
     let mut iv = [0u8; 16];
-    fi.read_exact(&mut iv)?;
-    let remaining = fsz - 16;
+    iv.copy_from_slice(&header.nonce_prefix);
+
+    let remaining = fsz - consumed;
 
     /* Ciphertext length = total - TAG(16) */
     if remaining < 16 {
@@ -663,14 +1508,13 @@ fn decrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<i32>
     let fo = OpenOptions::new().write(true).create(true).truncate(true).open(outfile)?;
     let mut bw = BufWriter::new(fo);
 
-    /* Compute H and J0 as in encryption */
-    let mut h = [0u8; 16]; compute_h(&mut h, key);
-    let hbe = load_be128(&h);
+    /* J0 from the IV; H/ghash_tables were already derived above */
     let mut j0 = [0u8; 16];
-    derive_j0(&mut j0, &iv, hbe);
+    derive_j0(&mut j0, &iv, &ghash_tables);
 
-    /* GHASH S over ciphertext */
+    /* GHASH S over AAD then ciphertext */
     let mut s = Be128 { hi: 0, lo: 0 };
+    ghash_aad(&mut s, &ghash_tables, &combined_aad);
 
     /* CTR starts at inc32(J0) */
     let mut ctr = j0;
@@ -696,11 +1540,11 @@ fn decrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<i32>
             cblk[..n].copy_from_slice(&buf[off..off+n]);
 
             // GHASH over ciphertext block
-            ghash_update(&mut s, hbe, &cblk);
+            ghash_update(&mut s, &ghash_tables, &cblk);
 
             // keystream
             let mut ks = [0u8; 16];
-            gost_encrypt_block(&ctr, &mut ks, key);
+            gost_encrypt_block(&ctr, &mut ks, &key);
             inc32(&mut ctr);
 
             // P = C XOR KS (only n bytes)
@@ -721,11 +1565,11 @@ fn decrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<i32>
 
     /* Finalize GHASH with lengths */
     let c_bits = (ciph_len as u64).wrapping_mul(8);
-    ghash_lengths_update(&mut s, hbe, 0, c_bits);
+    ghash_lengths_update(&mut s, &ghash_tables, combined_aad.len().wrapping_mul(8) as u64, c_bits);
 
     /* Compute expected tag: E_K(J0) XOR S */
     let mut ej0 = [0u8; 16];
-    gost_encrypt_block(&j0, &mut ej0, key);
+    gost_encrypt_block(&j0, &mut ej0, &key);
     let mut stmp = [0u8; 16];
     store_be128(s, &mut stmp);
     let mut tcalc = [0u8; 16];
@@ -743,47 +1587,419 @@ fn decrypt_file(infile: &str, outfile: &str, key: &[u64; 64]) -> io::Result<i32>
 }
 
 /* ---------------------- Derive GOST2-128 subkeys from password ---------------------- */
-fn derive_key_from_password(pwd: &str) -> [u64; 64] {
-    /* Follow the original code's hashing pipeline to build h4 then subkeys */
+
+const SALT_LEN: usize = 16;
+const ARGON2_SEED_LEN: usize = 64;
+
+/* Argon2id cost parameters, persisted per-file so decrypt_file can reproduce
+ * the exact same derivation regardless of what this build's defaults are. */
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct KdfParams {
+    t_cost: u32,
+    m_cost_kib: u32,
+    p_cost: u32,
+}
+
+const DEFAULT_KDF_PARAMS: KdfParams = KdfParams { t_cost: 3, m_cost_kib: 64 * 1024, p_cost: 1 };
+
+// Sanity bounds for cost parameters read back from a file header; guard
+// against a corrupted/malicious header forcing unbounded time or memory use.
+const MAX_KDF_T_COST: u32 = 64;
+const MAX_KDF_M_COST_KIB: u32 = 4 * 1024 * 1024; // 4 GiB
+const MAX_KDF_P_COST: u32 = 64;
+
+fn kdf_params_in_bounds(p: &KdfParams) -> bool {
+    p.t_cost >= 1 && p.t_cost <= MAX_KDF_T_COST
+        && p.m_cost_kib >= 8 && p.m_cost_kib <= MAX_KDF_M_COST_KIB
+        && p.p_cost >= 1 && p.p_cost <= MAX_KDF_P_COST
+}
+
+// t_cost(4) || m_cost_kib(4) || p_cost(4), all little-endian
+const KDF_PARAMS_LEN: usize = 12;
+
+fn write_kdf_params(w: &mut impl Write, p: &KdfParams) -> io::Result<()> {
+    w.write_all(&p.t_cost.to_le_bytes())?;
+    w.write_all(&p.m_cost_kib.to_le_bytes())?;
+    w.write_all(&p.p_cost.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_kdf_params(r: &mut impl Read, context: &str) -> io::Result<KdfParams> {
+    let mut buf = [0u8; KDF_PARAMS_LEN];
+    r.read_exact(&mut buf)?;
+    let params = KdfParams {
+        t_cost: u32::from_le_bytes(buf[0..4].try_into().unwrap()),
+        m_cost_kib: u32::from_le_bytes(buf[4..8].try_into().unwrap()),
+        p_cost: u32::from_le_bytes(buf[8..12].try_into().unwrap()),
+    };
+    if !kdf_params_in_bounds(&params) {
+        eprintln!("Malformed {}: KDF cost parameters out of bounds.", context);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad kdf params"));
+    }
+    Ok(params)
+}
+
+/* ---------------------- Versioned, self-describing file header ----------------------
+ * Everything about how a file was encrypted - KDF choice and cost, salt,
+ * single-shot vs. STREAM framing, nonce/counter prefix, chunk size, cipher -
+ * lives in one CBOR-encoded header ahead of the ciphertext, instead of being
+ * implied by a fixed byte layout. A new KDF or chunk size can be introduced
+ * by adding fields/variants and bumping HEADER_VERSION without breaking the
+ * ability to at least recognize and reject files from a different version. */
+
+const HEADER_MAGIC: [u8; 8] = *b"GOST2FC1";
+const HEADER_VERSION: u8 = 1;
+const MAX_HEADER_LEN: u32 = 4096;
+
+const KDF_ID_ARGON2ID: u8 = 0;
+const CIPHER_GOST2_128_GCM: u8 = 0;
+
+#[derive(Serialize, Deserialize)]
+struct FileHeader {
+    kdf_id: u8,
+    kdf: KdfParams,
+    salt: Vec<u8>,
+    format: u8,
+    nonce_prefix: Vec<u8>,
+    chunk_size: u32,
+    cipher: u8,
+}
+
+/* Writes magic || version || cbor_len(u32 LE) || cbor_body and returns the
+ * exact bytes written, so the caller can fold them into the AAD below
+ * without re-deriving them from the (already-parsed) header fields. */
+fn write_file_header(w: &mut impl Write, header: &FileHeader) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    ciborium::into_writer(header, &mut body).expect("CBOR encoding of file header cannot fail");
+    let mut raw = Vec::with_capacity(HEADER_MAGIC.len() + 1 + 4 + body.len());
+    raw.extend_from_slice(&HEADER_MAGIC);
+    raw.push(HEADER_VERSION);
+    raw.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    raw.extend_from_slice(&body);
+    w.write_all(&raw)?;
+    Ok(raw)
+}
+
+fn read_file_header(r: &mut impl Read) -> io::Result<(FileHeader, Vec<u8>)> {
+    let mut magic = [0u8; 8];
+    r.read_exact(&mut magic)?;
+    if magic != HEADER_MAGIC {
+        eprintln!("Not a gost2gcm file (bad magic bytes).");
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad magic"));
+    }
+    let mut version = [0u8; 1];
+    r.read_exact(&mut version)?;
+    if version[0] != HEADER_VERSION {
+        eprintln!("Unsupported file format version {} (this build only understands version {}).", version[0], HEADER_VERSION);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported header version"));
+    }
+    let mut len_buf = [0u8; 4];
+    r.read_exact(&mut len_buf)?;
+    let len = u32::from_le_bytes(len_buf);
+    if len > MAX_HEADER_LEN {
+        eprintln!("Malformed header: CBOR body too large ({} bytes).", len);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "header too large"));
+    }
+    let mut body = vec![0u8; len as usize];
+    r.read_exact(&mut body)?;
+
+    let header: FileHeader = ciborium::from_reader(&body[..])
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("malformed CBOR header: {}", e)))?;
+    if header.kdf_id != KDF_ID_ARGON2ID {
+        eprintln!("Unsupported KDF identifier {} in header.", header.kdf_id);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported kdf"));
+    }
+    if !kdf_params_in_bounds(&header.kdf) {
+        eprintln!("Malformed header: KDF cost parameters out of bounds.");
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad kdf params"));
+    }
+    if header.cipher != CIPHER_GOST2_128_GCM {
+        eprintln!("Unsupported cipher identifier {} in header.", header.cipher);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "unsupported cipher"));
+    }
+
+    let mut raw = Vec::with_capacity(HEADER_MAGIC.len() + 1 + 4 + body.len());
+    raw.extend_from_slice(&magic);
+    raw.extend_from_slice(&version);
+    raw.extend_from_slice(&len_buf);
+    raw.extend_from_slice(&body);
+    Ok((header, raw))
+}
+
+/* Read just the salt out of a file header, without deriving anything or
+ * touching the rest of the file, so callers can try a keyring lookup before
+ * prompting for a password. */
+fn peek_header_salt(infile: &str) -> io::Result<[u8; SALT_LEN]> {
+    let mut fi = File::open(infile)?;
+    let (header, _) = read_file_header(&mut fi)?;
+    if header.salt.len() != SALT_LEN {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "bad salt length"));
+    }
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(&header.salt);
+    Ok(salt)
+}
+
+/* The original file basename (not the full path), so moving a ciphertext
+ * file to another directory doesn't break decryption, but renaming it or
+ * swapping one file's body under another file's name does. */
+fn header_aad_filename(infile: &str) -> Vec<u8> {
+    Path::new(infile)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned().into_bytes())
+        .unwrap_or_default()
+}
+
+/* Bind the on-disk header - everything that isn't itself encrypted - into the
+ * GHASH associated data, on top of whatever AAD the caller passed on the
+ * command line. This way a flipped byte anywhere in the magic, version, KDF
+ * parameters, salt, format, nonce/counter prefix or chunk size surfaces as a
+ * tag mismatch instead of silently deriving the wrong key or decrypting
+ * under the wrong nonce. Fields are length-prefixed (u32 LE) so the
+ * variable-length filename can't be confused with the fixed-length fields
+ * around it, and the caller-supplied AAD is appended last, also
+ * length-prefixed. */
+fn bind_header_aad(header_bytes: &[u8], filename: &[u8], user_aad: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(4 + header_bytes.len() + 4 + filename.len() + 4 + user_aad.len());
+    out.extend_from_slice(&(header_bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(header_bytes);
+    out.extend_from_slice(&(filename.len() as u32).to_le_bytes());
+    out.extend_from_slice(filename);
+    out.extend_from_slice(&(user_aad.len() as u32).to_le_bytes());
+    out.extend_from_slice(user_aad);
+    out
+}
+
+/* Memory-hard key derivation: Argon2id(password, salt, params) produces a
+ * 64-byte pseudorandom seed, which is then run through the existing
+ * HashState -> create_keys pipeline exactly as the raw password used to be,
+ * so the downstream GOST2 subkey schedule is unchanged. */
+fn derive_key_from_password(pwd: &str, salt: &[u8; SALT_LEN], params: &KdfParams) -> Secret<[u64; 64]> {
+    let argon2_params = Params::new(params.m_cost_kib, params.t_cost, params.p_cost, Some(ARGON2_SEED_LEN))
+        .expect("valid Argon2 parameters");
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params);
+
+    let mut seed = Secret::new([0u8; ARGON2_SEED_LEN]);
+    argon2
+        .hash_password_into(pwd.as_bytes(), salt, &mut *seed)
+        .expect("Argon2id derivation failed");
+
     let mut hs = HashState::new();
-    hs.hashing(pwd.as_bytes());
-    let h4 = hs.end_hash();
-    create_keys(&h4)
+    hs.hashing(&seed[..]);
+    let h4 = Secret::new(hs.end_hash());
+    Secret::new(create_keys(&h4))
 }
 
+/* Either a password to derive subkeys from, or an already-resolved subkey
+ * schedule (e.g. fetched from the kernel keyring) - lets callers skip the
+ * Argon2id derivation, and main() skip the interactive prompt, whenever the
+ * key is already known. */
+enum Credential<'a> {
+    Password(&'a str),
+    Key(Box<Secret<[u64; 64]>>),
+}
+
+impl<'a> Credential<'a> {
+    fn is_password(&self) -> bool {
+        matches!(self, Credential::Password(_))
+    }
+
+    fn resolve(self, salt: &[u8; SALT_LEN], params: &KdfParams) -> Secret<[u64; 64]> {
+        match self {
+            Credential::Password(pwd) => derive_key_from_password(pwd, salt, params),
+            Credential::Key(key) => *key,
+        }
+    }
+}
+
+/* Thin wrappers so call sites don't need `#[cfg(target_os = "linux")]`
+ * scattered through them: off Linux, keyring lookup always misses and
+ * caching is a no-op (with a warning if the user actually asked for it). */
+#[cfg(target_os = "linux")]
+fn keyring_lookup(salt: &[u8; SALT_LEN], use_keyring: bool, wait_for_key: bool) -> Option<Secret<[u64; 64]>> {
+    if !use_keyring {
+        return None;
+    }
+    if let Some(key) = keyring::search(salt) {
+        return Some(Secret::new(key));
+    }
+    if wait_for_key {
+        return Some(Secret::new(keyring::wait_for(salt)));
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn keyring_lookup(_salt: &[u8; SALT_LEN], use_keyring: bool, wait_for_key: bool) -> Option<Secret<[u64; 64]>> {
+    if use_keyring || wait_for_key {
+        eprintln!("Kernel keyring integration is only available on Linux; ignoring --use-keyring/--wait-for-key.");
+    }
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn keyring_cache(salt: &[u8; SALT_LEN], key: &Secret<[u64; 64]>, use_keyring: bool) {
+    if use_keyring {
+        keyring::add(salt, key);
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn keyring_cache(_salt: &[u8; SALT_LEN], _key: &Secret<[u64; 64]>, _use_keyring: bool) {}
+
 // ---------------------- CLI / Main ----------------------
 
 fn usage(prog: &str) {
-    eprintln!("Usage: {} c|d <input_file>", prog);
+    eprintln!(
+        "Usage: {} c|d|mac|verify-mac <input_file> [--aad <string>] [--kdf-time-cost <n>] [--kdf-mem-kib <n>] [--kdf-parallelism <n>] [--legacy-single-tag] [--use-keyring] [--wait-for-key]",
+        prog
+    );
+    eprintln!("  c           encrypt <input_file>, writing <input_file>.gost2");
+    eprintln!("  d           decrypt <input_file>, writing the plaintext alongside it");
+    eprintln!("  mac         write a detached GOST2-CMAC tag to <input_file>.gmac");
+    eprintln!("  verify-mac  check <input_file> against its <input_file>.gmac tag");
+    eprintln!("  --use-keyring   cache the derived key in the Linux kernel user keyring,");
+    eprintln!("                  keyed by the file's salt, and reuse it instead of prompting");
+    eprintln!("                  again for the same file (Linux only; ignored elsewhere)");
+    eprintln!("  --wait-for-key  with d/verify-mac and --use-keyring, block until another");
+    eprintln!("                  process provisions the key instead of prompting for the password");
 }
 
 fn main() -> io::Result<()> {
     let args = env::args().collect::<Vec<_>>();
-    if args.len() != 3 {
+    if args.len() < 3 {
         usage(&args.get(0).cloned().unwrap_or_else(|| "gost2gcm".to_string()));
         std::process::exit(2);
     }
     let mode = &args[1];
     let infile = &args[2];
 
-    let pwd = pw::read_password("Enter password: ")?;
-    // Init GOST2 tables and derive subkeys from password
+    // Optional associated data, authenticated but left unencrypted (e.g. the
+    // original filename, or a caller-chosen header string); optional
+    // overrides of the encrypt-side Argon2id cost parameters (decrypt always
+    // reads them back from the file header); and an opt-out of the default
+    // STREAM chunked framing (decrypt always follows the format marker
+    // stored in the file, so this only affects encryption).
+    let mut aad: Vec<u8> = Vec::new();
+    let mut kdf_params = DEFAULT_KDF_PARAMS;
+    let mut legacy_single_tag = false;
+    let mut use_keyring = false;
+    let mut wait_for_key = false;
+    let mut i = 3usize;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--aad" if i + 1 < args.len() => {
+                aad = args[i + 1].clone().into_bytes();
+                i += 2;
+            }
+            "--kdf-time-cost" if i + 1 < args.len() => {
+                kdf_params.t_cost = args[i + 1].parse().unwrap_or_else(|_| {
+                    usage(&args[0]);
+                    std::process::exit(2);
+                });
+                i += 2;
+            }
+            "--kdf-mem-kib" if i + 1 < args.len() => {
+                kdf_params.m_cost_kib = args[i + 1].parse().unwrap_or_else(|_| {
+                    usage(&args[0]);
+                    std::process::exit(2);
+                });
+                i += 2;
+            }
+            "--kdf-parallelism" if i + 1 < args.len() => {
+                kdf_params.p_cost = args[i + 1].parse().unwrap_or_else(|_| {
+                    usage(&args[0]);
+                    std::process::exit(2);
+                });
+                i += 2;
+            }
+            "--legacy-single-tag" => {
+                legacy_single_tag = true;
+                i += 1;
+            }
+            "--use-keyring" => {
+                use_keyring = true;
+                i += 1;
+            }
+            "--wait-for-key" => {
+                wait_for_key = true;
+                i += 1;
+            }
+            _ => {
+                usage(&args[0]);
+                std::process::exit(2);
+            }
+        }
+    }
+    if !kdf_params_in_bounds(&kdf_params) {
+        eprintln!("KDF cost parameters out of bounds.");
+        std::process::exit(2);
+    }
+
+    // Init GOST2 tables
     kboxinit();
-    let key = derive_key_from_password(&pwd);
-    // Zero password buffer after use (best effort)
-    drop(pwd);
 
-    // Build output file name
+    // For "c"/"mac" a fresh salt is always generated, so there is nothing to
+    // look up yet - a password is always required, and is cached afterwards
+    // if --use-keyring was given. For "d"/"verify-mac" the salt already
+    // exists on disk, so try the keyring first and only prompt on a miss.
     if mode.eq_ignore_ascii_case("c") {
+        let pwd = Secret::new(pw::read_password("Enter password: ")?);
         let outfile = add_suffix_gost2(infile);
-        if let Err(e) = encrypt_file(infile, &outfile, &key) {
+        let result = encrypt_file(infile, &outfile, Credential::Password(&pwd), &kdf_params, &aad, !legacy_single_tag, use_keyring);
+        drop(pwd);
+        if let Err(e) = result {
             eprintln!("Encryption error: {}", e);
             std::process::exit(1);
         }
         Ok(())
     } else if mode.eq_ignore_ascii_case("d") {
         let outfile = strip_suffix_gost2(infile);
-        match decrypt_file(infile, &outfile, &key)? {
+        let cached = peek_header_salt(infile).ok().and_then(|salt| keyring_lookup(&salt, use_keyring, wait_for_key));
+        let pwd: Option<Secret<String>>;
+        let cred;
+        match cached {
+            Some(key) => {
+                cred = Credential::Key(Box::new(key));
+                pwd = None;
+            }
+            None => {
+                pwd = Some(Secret::new(pw::read_password("Enter password: ")?));
+                cred = Credential::Password(pwd.as_ref().unwrap());
+            }
+        }
+        let result = decrypt_file(infile, &outfile, cred, &aad, use_keyring)?;
+        drop(pwd);
+        match result {
+            0 => Ok(()),
+            _ => std::process::exit(1),
+        }
+    } else if mode.eq_ignore_ascii_case("mac") {
+        let pwd = Secret::new(pw::read_password("Enter password: ")?);
+        let result = mac_file(infile, Credential::Password(&pwd), &kdf_params, use_keyring);
+        drop(pwd);
+        if let Err(e) = result {
+            eprintln!("MAC error: {}", e);
+            std::process::exit(1);
+        }
+        Ok(())
+    } else if mode.eq_ignore_ascii_case("verify-mac") {
+        let cached = peek_mac_salt(infile).ok().and_then(|salt| keyring_lookup(&salt, use_keyring, wait_for_key));
+        let pwd: Option<Secret<String>>;
+        let cred;
+        match cached {
+            Some(key) => {
+                cred = Credential::Key(Box::new(key));
+                pwd = None;
+            }
+            None => {
+                pwd = Some(Secret::new(pw::read_password("Enter password: ")?));
+                cred = Credential::Password(pwd.as_ref().unwrap());
+            }
+        }
+        let result = verify_mac_file(infile, cred, use_keyring)?;
+        drop(pwd);
+        match result {
             0 => Ok(()),
             _ => std::process::exit(1),
         }
@@ -792,3 +2008,170 @@ fn main() -> io::Result<()> {
         std::process::exit(2);
     }
 }
+
+#[cfg(test)]
+mod ghash_tests {
+    use super::*;
+
+    // xorshift64* — deterministic, dependency-free PRNG; good enough to
+    // generate spot-check vectors for a cross-implementation equivalence test.
+    fn xorshift64(state: &mut u64) -> u64 {
+        let mut x = *state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        *state = x;
+        x
+    }
+
+    fn rand_be128(state: &mut u64) -> Be128 {
+        Be128 { hi: xorshift64(state), lo: xorshift64(state) }
+    }
+
+    /* The bit-serial `gf_mult` is the trusted reference. The windowed-table
+     * path and the CLMUL path must both agree with it on every input, or
+     * previously-authenticated ciphertexts silently stop verifying. */
+    #[test]
+    fn ghash_paths_agree_with_reference() {
+        let mut state = 0x9E3779B97F4A7C15u64;
+        for _ in 0..2000 {
+            let h = rand_be128(&mut state);
+            let x = rand_be128(&mut state);
+            if h.hi == 0 && h.lo == 0 {
+                continue;
+            }
+            let want = gf_mult(x, h);
+
+            #[allow(unused_mut)]
+            let mut tables = GhashTables::new(h);
+            #[cfg(target_arch = "x86_64")]
+            {
+                tables.clmul = false;
+            }
+            let got_table = tables.mult(x);
+            assert_eq!(
+                (got_table.hi, got_table.lo),
+                (want.hi, want.lo),
+                "windowed-table GHASH path disagrees with bit-serial reference"
+            );
+
+            #[cfg(target_arch = "x86_64")]
+            if is_x86_feature_detected!("pclmulqdq") && is_x86_feature_detected!("sse2") {
+                let got_clmul = unsafe { clmul::gfmul(x, h) };
+                assert_eq!(
+                    (got_clmul.hi, got_clmul.lo),
+                    (want.hi, want.lo),
+                    "CLMUL GHASH path disagrees with bit-serial reference"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ghash_zero_and_identity_vectors() {
+        let zero = Be128 { hi: 0, lo: 0 };
+        let h = Be128 { hi: 0x0123_4567_89ab_cdef, lo: 0xfedc_ba98_7654_3210 };
+        let tables = GhashTables::new(h);
+        assert_eq!((tables.mult(zero).hi, tables.mult(zero).lo), (0, 0));
+
+        // The multiplicative identity is x^0, the MSB in GCM's bit order.
+        let one = Be128 { hi: 0x8000_0000_0000_0000, lo: 0 };
+        assert_eq!((tables.mult(one).hi, tables.mult(one).lo), (h.hi, h.lo));
+
+        let allone = Be128 { hi: u64::MAX, lo: u64::MAX };
+        let want = gf_mult(allone, allone);
+        let got = GhashTables::new(allone).mult(allone);
+        assert_eq!((got.hi, got.lo), (want.hi, want.lo));
+    }
+}
+
+#[cfg(test)]
+mod aead_mac_tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // A constant-byte h4 is a degenerate, non-representative key (every
+    // round's subkey material is identical, which can fix points like
+    // E_K(0) = 0); hash a real string instead, the same way every other
+    // caller in this file derives subkeys.
+    fn test_key(seed: &str) -> [u64; 64] {
+        // `main()` calls this once before touching the cipher; tests have no
+        // `main()` to do that for them, so call it directly (it's a no-op
+        // after the first call, same as the `Once` it wraps).
+        kboxinit();
+        let mut hs = HashState::new();
+        hs.hashing(seed.as_bytes());
+        let h4 = hs.end_hash();
+        create_keys(&h4)
+    }
+
+    fn test_tables(key: &[u64; 64]) -> GhashTables {
+        let mut h = [0u8; 16];
+        compute_h(&mut h, key);
+        GhashTables::new(load_be128(&h))
+    }
+
+    #[test]
+    fn gcm_segment_round_trips_and_rejects_tampering() {
+        let key = test_key("gcm test passphrase one");
+        let tables = test_tables(&key);
+        let nonce = [0x11u8; 12];
+        let aad = b"associated data";
+        let plaintext = b"GCM segment round-trip test message, spanning more than one block!";
+
+        let (ciphertext, tag) = gcm_seal_segment(&key, &tables, &nonce, aad, plaintext);
+        let recovered = gcm_open_segment(&key, &tables, &nonce, aad, &ciphertext, &tag)
+            .expect("valid tag must open");
+        assert_eq!(recovered, plaintext);
+
+        let mut bad_ct = ciphertext.clone();
+        bad_ct[0] ^= 1;
+        assert!(gcm_open_segment(&key, &tables, &nonce, aad, &bad_ct, &tag).is_none(),
+            "tampered ciphertext must not verify");
+
+        let mut bad_tag = tag;
+        bad_tag[0] ^= 1;
+        assert!(gcm_open_segment(&key, &tables, &nonce, aad, &ciphertext, &bad_tag).is_none(),
+            "tampered tag must not verify");
+
+        assert!(gcm_open_segment(&key, &tables, &nonce, b"wrong aad", &ciphertext, &tag).is_none(),
+            "mismatched AAD must not verify");
+    }
+
+    #[test]
+    fn gcm_segment_empty_plaintext_round_trips() {
+        let key = test_key("gcm test passphrase two");
+        let tables = test_tables(&key);
+        let nonce = [0x22u8; 12];
+        let (ciphertext, tag) = gcm_seal_segment(&key, &tables, &nonce, b"", b"");
+        assert!(ciphertext.is_empty());
+        assert_eq!(gcm_open_segment(&key, &tables, &nonce, b"", &ciphertext, &tag), Some(Vec::new()));
+    }
+
+    #[test]
+    fn cmac_is_deterministic_and_detects_tampering() {
+        let key = test_key("cmac test passphrase");
+        let message = b"CMAC test message, long enough to span two 16-byte blocks.";
+
+        let mac_a = gost_cmac_file(&mut Cursor::new(message.to_vec()), &key).unwrap();
+        let mac_b = gost_cmac_file(&mut Cursor::new(message.to_vec()), &key).unwrap();
+        assert_eq!(mac_a, mac_b, "same message/key must reproduce the same tag");
+
+        let mut tampered = message.to_vec();
+        tampered[0] ^= 1;
+        let mac_tampered = gost_cmac_file(&mut Cursor::new(tampered), &key).unwrap();
+        assert_ne!(mac_a, mac_tampered, "a one-bit change must change the tag");
+
+        let other_key = test_key("a different cmac passphrase");
+        let mac_other_key = gost_cmac_file(&mut Cursor::new(message.to_vec()), &other_key).unwrap();
+        assert_ne!(mac_a, mac_other_key, "a different key must change the tag");
+    }
+
+    #[test]
+    fn cmac_empty_message() {
+        let key = test_key("cmac empty message passphrase");
+        let mac_a = gost_cmac_file(&mut Cursor::new(Vec::new()), &key).unwrap();
+        let mac_b = gost_cmac_file(&mut Cursor::new(Vec::new()), &key).unwrap();
+        assert_eq!(mac_a, mac_b);
+    }
+}